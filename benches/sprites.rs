@@ -0,0 +1,76 @@
+//! Draws thousands of sprites a frame and reports how long frames take, and whether the
+//! instance buffer had to grow after the first one. `render_to_image` keeps its target and
+//! staging buffer between frames, so the times only add one small readback on top of drawing.
+//! Run with `cargo bench`.
+
+use std::time::Instant;
+
+use cowboy_dot_exe::graphics::{
+    assets::AssetLoader,
+    pixel_art::{sprite::Origin, sprite_sheet::*},
+    *,
+};
+use glam::Vec2;
+use winit::dpi::PhysicalSize;
+
+const SPRITES: u32 = 5_000;
+const FRAMES: u32 = 50;
+
+fn main() {
+    let size = PhysicalSize::new(384, 216);
+    let Some(mut renderer) = pollster::block_on(Renderer::new_headless(384, 216, size)) else {
+        println!("no adapter to render with, skipping");
+        return;
+    };
+
+    let sprite = renderer
+        .load_sprite(Origin::Center, "twelve_string/awake.png")
+        .unwrap();
+    let sheet = renderer
+        .load_sprite_sheet(
+            Origin::Center,
+            "player/run.png",
+            6,
+            FrameRate::Constant(0.1),
+            Orientation::Horizontal,
+        )
+        .unwrap();
+
+    let build = |frame: u32| {
+        let mut dq = DrawQueue::new();
+        for i in 0..SPRITES {
+            let position = Vec2::new((i * 7 % 384) as f32, (i * 13 % 216) as f32);
+            let params = DrawParams {
+                alpha: if i % 4 == 0 { 0.5 } else { 1.0 },
+                ..DrawParams::from_pos(position)
+            };
+            if i % 2 == 0 {
+                dq.sprite(&sprite, params);
+            } else {
+                dq.sheet(&sheet, (frame + i) % 6, params);
+            }
+        }
+        dq
+    };
+
+    renderer.render_to_image(build(0), size);
+    let capacity = renderer.instance_capacity();
+
+    let start = Instant::now();
+    for frame in 1..=FRAMES {
+        renderer.render_to_image(build(frame), size);
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{} sprites: {:.2} ms a frame over {} frames",
+        SPRITES,
+        elapsed.as_secs_f64() * 1000.0 / FRAMES as f64,
+        FRAMES
+    );
+    println!(
+        "instance buffer holds {} instances after the first frame, {} after the last",
+        capacity,
+        renderer.instance_capacity()
+    );
+}
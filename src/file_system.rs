@@ -5,12 +5,25 @@ pub enum LoadError {
     PathNotFound(String),
     CantLoadTexture(String),
     WrongTileLayerType(String),
+    InvalidPalette(String),
+    MissingObject(String),
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    CantCreateDirectory(String),
+    CantSaveImage(String),
+    CantWriteFile(String),
 }
 
 pub fn to_asset_path(path: &str) -> String {
     String::from("./assets/") + path
 }
 
+pub fn to_screenshot_path(path: &str) -> String {
+    String::from("./screenshots/") + path
+}
+
 pub fn load_image(path: &str) -> Result<DynamicImage, LoadError> {
     let pwd = to_asset_path(path);
     let err_dir = pwd.to_owned();
@@ -24,3 +37,21 @@ pub fn load_image(path: &str) -> Result<DynamicImage, LoadError> {
         Err(_) => return Err(LoadError::CantLoadTexture(err_dir)),
     }
 }
+
+pub fn create_parent_dir(path: &str) -> Result<(), SaveError> {
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        if std::fs::create_dir_all(dir).is_err() {
+            return Err(SaveError::CantCreateDirectory(dir.display().to_string()));
+        }
+    }
+    Ok(())
+}
+
+pub fn save_image(path: &str, image: &RgbaImage) -> Result<(), SaveError> {
+    create_parent_dir(path)?;
+
+    match image.save(path) {
+        Ok(()) => Ok(()),
+        Err(_) => Err(SaveError::CantSaveImage(path.to_owned())),
+    }
+}
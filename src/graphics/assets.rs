@@ -0,0 +1,347 @@
+//! Loading sprites, sprite sheets and tile maps. This is shared by [`Renderer`](super::Renderer),
+//! which packs every image into its atlas, and
+//! [`SoftwareRenderer`](super::software::SoftwareRenderer), which only keeps them on the CPU and
+//! so doesn't need a device.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    f32::consts::TAU,
+    rc::Rc,
+};
+
+use glam::*;
+use image::RgbaImage;
+use itertools::iproduct;
+
+use crate::file_system::{self, LoadError};
+
+use super::pixel_art::{
+    atlas::AtlasTexture,
+    rotsprite,
+    sprite::*,
+    sprite_sheet::*,
+    tilemap::{
+        ImageLayer, MapObject, ObjectLayer, Tile, TileAnimation, TileLayer, TileMap, Tileset,
+    },
+};
+
+pub trait AssetLoader {
+    /// Makes a texture sprites and tilesets can be drawn from.
+    fn texture(&self, image: RgbaImage) -> AtlasTexture;
+
+    /// Ids taken by sprites and sprite sheets that are still alive.
+    fn sprite_ids(&self) -> &Rc<RefCell<HashSet<usize>>>;
+
+    fn load_tilemap(&self, path: &str) -> Result<TileMap, LoadError> {
+        let path = &file_system::to_asset_path(path);
+        let mut loader = tiled::Loader::new();
+        let tmx = match loader.load_tmx_map(path) {
+            Ok(tile_map) => tile_map,
+            Err(_) => return Err(LoadError::PathNotFound(path.to_owned())),
+        };
+
+        let mut tile_layers = HashMap::new();
+        let mut image_layers = HashMap::new();
+        let mut object_layers = HashMap::new();
+        // loaded the first time a layer uses them, so layers sharing a tileset share its place in
+        // the atlas too
+        let mut tilesets: Vec<Option<Rc<Tileset>>> = vec![None; tmx.tilesets().len()];
+
+        for layer in tmx.layers() {
+            match layer.layer_type() {
+                tiled::LayerType::TileLayer(tile_layer) => {
+                    let name = layer.name.clone();
+                    let mut layer_tilesets: Vec<Rc<Tileset>> = vec![];
+                    // from the map's tileset indices to the layer's
+                    let mut tileset_indices = HashMap::new();
+                    let mut tiles = vec![];
+                    for (x, y) in tile_coords(&tile_layer) {
+                        let Some(t) = tile_layer.get_tile(x, y) else {
+                            continue;
+                        };
+                        // tiled has already worked out which tileset the tile's gid falls in, and
+                        // made its id local to that tileset
+                        let map_index = t.tileset_index();
+                        let tileset = match tileset_indices.get(&map_index) {
+                            Some(index) => *index,
+                            None => {
+                                let loaded = match &tilesets[map_index] {
+                                    Some(loaded) => loaded.clone(),
+                                    None => {
+                                        let loaded = Rc::new(self.load_tileset(t.get_tileset())?);
+                                        tilesets[map_index] = Some(loaded.clone());
+                                        loaded
+                                    }
+                                };
+                                layer_tilesets.push(loaded);
+                                tileset_indices.insert(map_index, layer_tilesets.len() - 1);
+                                layer_tilesets.len() - 1
+                            }
+                        };
+                        tiles.push(Tile {
+                            flip_x: t.flip_h,
+                            flip_y: t.flip_v,
+                            id: t.id(),
+                            tileset,
+                            x,
+                            y,
+                        });
+                    }
+
+                    let mut layer = TileLayer {
+                        name: name.clone(),
+                        tile_width: tmx.tile_width,
+                        tile_height: tmx.tile_height,
+                        tilesets: layer_tilesets,
+                        chunks: vec![],
+                        time: 0.0,
+                        properties: layer.properties.clone(),
+                        tiles,
+                    };
+                    layer.build_chunks();
+
+                    tile_layers.insert(name, layer);
+                }
+                tiled::LayerType::ImageLayer(image_layer) => {
+                    let sprite = self.load_sprite(
+                        Origin::TopLeft,
+                        &image_layer.image.as_ref().unwrap().source.to_str().unwrap()
+                            ["./assets".len()..],
+                    )?;
+
+                    image_layers.insert(
+                        layer.name.clone(),
+                        ImageLayer {
+                            sprite,
+                            repeat_x: match layer.properties.get("Repeat X") {
+                                Some(tiled::PropertyValue::BoolValue(b)) => *b,
+                                _ => false,
+                            },
+                            repeat_y: match layer.properties.get("Repeat Y") {
+                                Some(tiled::PropertyValue::BoolValue(b)) => *b,
+                                _ => false,
+                            },
+                            position: Vec2::new(layer.offset_x, layer.offset_y),
+                        },
+                    );
+                }
+                tiled::LayerType::ObjectLayer(object_layer) => {
+                    let offset = Vec2::new(layer.offset_x, layer.offset_y);
+                    let objects = object_layer
+                        .objects()
+                        .map(|object| MapObject::from_tiled(&object, offset))
+                        .collect();
+
+                    object_layers.insert(
+                        layer.name.clone(),
+                        ObjectLayer {
+                            name: layer.name.clone(),
+                            objects,
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        Ok(TileMap {
+            tile_layers,
+            image_layers,
+            object_layers,
+            tile_width: tmx.tile_width,
+            tile_height: tmx.tile_height,
+        })
+    }
+
+    /// Makes a texture out of a Tiled tileset's image.
+    fn load_tileset(&self, tileset: &tiled::Tileset) -> Result<Tileset, LoadError> {
+        // image collections have an image per tile, and no columns to find tiles in
+        let Some(image) = &tileset.image else {
+            return Err(LoadError::CantLoadTexture(tileset.name.clone()));
+        };
+        let path = &image.source.to_str().unwrap()["./assets".len()..];
+        let image = file_system::load_image(path)?.to_rgba8();
+        Ok(Tileset {
+            texture: self.texture(image),
+            columns: tileset.columns,
+            tile_width: tileset.tile_width,
+            tile_height: tileset.tile_height,
+            margin: tileset.margin,
+            spacing: tileset.spacing,
+            animations: tileset
+                .tiles()
+                .filter_map(|(id, tile)| {
+                    // an empty animation has nothing to show, so the tile is drawn as is
+                    let frames = tile.animation.as_ref().filter(|f| !f.is_empty())?;
+                    Some((
+                        id,
+                        TileAnimation {
+                            // tiled counts in milliseconds
+                            frames: frames
+                                .iter()
+                                .map(|frame| (frame.tile_id, frame.duration as f32 / 1000.0))
+                                .collect(),
+                        },
+                    ))
+                })
+                .collect(),
+            properties: tileset
+                .tiles()
+                .filter(|(_, tile)| !tile.properties.is_empty())
+                .map(|(id, tile)| (id, tile.properties.clone()))
+                .collect(),
+            collisions: tileset
+                .tiles()
+                .filter_map(|(id, tile)| {
+                    let collision = tile.collision.as_ref()?;
+                    let objects = collision
+                        .object_data()
+                        .iter()
+                        .map(|object| MapObject::from_tiled(object, Vec2::ZERO))
+                        .collect();
+                    Some((id, objects))
+                })
+                .collect(),
+        })
+    }
+
+    fn load_sprite(&self, origin: Origin, path: &str) -> Result<Sprite, LoadError> {
+        let image = file_system::load_image(path)?.to_rgba8();
+        Ok(self.create_sprite(origin, image))
+    }
+
+    fn create_sprite(&self, origin: Origin, image: RgbaImage) -> Sprite {
+        let texture = self.texture(image);
+        let ids = self.sprite_ids().clone();
+        let id = {
+            let mut id = 0;
+            loop {
+                if !ids.as_ref().borrow().contains(&id) {
+                    ids.borrow_mut().insert(id);
+                    break id;
+                }
+                id += 1;
+            }
+        };
+
+        Sprite {
+            texture,
+            origin,
+            id,
+            ids,
+            rotations: vec![],
+        }
+    }
+
+    fn load_sprite_sheet(
+        &self,
+        origin: Origin,
+        path: &str,
+        count: u8,
+        frame_rate: FrameRate,
+        orientation: Orientation,
+    ) -> Result<SpriteSheet, LoadError> {
+        Ok(self.create_sprite_sheet(
+            origin,
+            file_system::load_image(path)?.to_rgba8(),
+            count,
+            frame_rate,
+            orientation,
+        ))
+    }
+
+    fn create_sprite_sheet(
+        &self,
+        origin: Origin,
+        image: RgbaImage,
+        count: u8,
+        frame_rate: FrameRate,
+        orientation: Orientation,
+    ) -> SpriteSheet {
+        let texture = self.texture(image);
+        let ids = self.sprite_ids().clone();
+        let id = {
+            let mut id = 0;
+            loop {
+                if !ids.as_ref().borrow().contains(&id) {
+                    ids.borrow_mut().insert(id);
+                    break id;
+                }
+                id += 1;
+            }
+        };
+
+        SpriteSheet {
+            count,
+            frame_rate,
+            orientation,
+            id,
+            ids,
+            texture,
+            origin,
+            rotations: vec![],
+        }
+    }
+
+    /// Pre-renders `steps` evenly spaced rotations of the sprite with RotSprite. Drawing it with
+    /// [`Rotation::Radians`](super::Rotation::Radians) then uses the closest one instead of
+    /// rotating it in the shader, unless it's scaled differently along x and y.
+    fn cache_rotations(&self, sprite: &mut Sprite, steps: u32) {
+        sprite.rotations = (0..steps)
+            .map(|i| {
+                let radians = i as f32 * TAU / steps as f32;
+                let (rotated, pivot) =
+                    rotsprite::rotate(&sprite.texture.image, sprite.origin(), radians);
+                self.create_sprite(Origin::Precise(pivot), rotated)
+            })
+            .collect();
+    }
+
+    /// Like [`AssetLoader::cache_rotations`], for every frame of a sprite sheet.
+    fn cache_sheet_rotations(&self, sprite_sheet: &mut SpriteSheet, steps: u32) {
+        sprite_sheet.rotations = (0..steps)
+            .map(|i| {
+                let radians = i as f32 * TAU / steps as f32;
+                let (rotated, pivot) = rotsprite::rotate_frames(
+                    &sprite_sheet.texture.image,
+                    sprite_sheet.size(),
+                    sprite_sheet.count as u32,
+                    matches!(sprite_sheet.orientation, Orientation::Horizontal),
+                    sprite_sheet.origin(),
+                    radians,
+                );
+                self.create_sprite_sheet(
+                    Origin::Precise(pivot),
+                    rotated,
+                    sprite_sheet.count,
+                    sprite_sheet.frame_rate.clone(),
+                    sprite_sheet.orientation,
+                )
+            })
+            .collect();
+    }
+}
+
+/// Where a layer's tiles could be, column by column from the left. Infinite maps only store the
+/// chunks that have tiles in them, which can be anywhere, including at negative coordinates.
+fn tile_coords(tile_layer: &tiled::TileLayer) -> Vec<(i32, i32)> {
+    match tile_layer {
+        tiled::TileLayer::Finite(finite) => {
+            iproduct!(0..finite.width() as i32, 0..finite.height() as i32).collect()
+        }
+        tiled::TileLayer::Infinite(infinite) => {
+            let (width, height) = (tiled::Chunk::WIDTH as i32, tiled::Chunk::HEIGHT as i32);
+            let mut coords: Vec<(i32, i32)> = infinite
+                .chunks()
+                .flat_map(|((chunk_x, chunk_y), _)| {
+                    iproduct!(0..width, 0..height)
+                        .map(move |(x, y)| (chunk_x * width + x, chunk_y * height + y))
+                })
+                .collect();
+            // chunks come in no particular order
+            coords.sort();
+            coords
+        }
+    }
+}
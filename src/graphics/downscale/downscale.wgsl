@@ -25,7 +25,130 @@ var t_texture: texture_2d<f32>;
 @group(0) @binding(1)
 var t_sampler: sampler;
 
+struct DownscaleData {
+    mode: u32,
+    pixel_prec: u32,
+    palette_size: u32,
+    dither: f32,
+}
+@group(1) @binding(0)
+var<uniform> data: DownscaleData;
+// not srgb, so colours come out the way they're stored
+@group(1) @binding(1)
+var t_palette: texture_2d<f32>;
+
+fn load(texel: vec2<i32>, size: vec2<i32>) -> vec4<f32> {
+    return textureLoad(t_texture, clamp(texel, vec2<i32>(0), size - 1), 0);
+}
+
+// center of the supersampled block making up a base resolution pixel
+fn load_pixel(pixel: vec2<f32>, size: vec2<i32>) -> vec4<f32> {
+    let prec = i32(data.pixel_prec);
+    return load(vec2<i32>(floor(pixel)) * prec + prec / 2, size);
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let high = pow((c + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// 4x4 bayer matrix, from 0 to 1
+fn bayer(pixel: vec2<i32>) -> f32 {
+    let x = pixel.x & 3;
+    let y = pixel.y & 3;
+    let a = (x ^ y) & 1;
+    let b = ((x ^ y) >> 1u) & 1;
+    let c = y & 1;
+    let d = (y >> 1u) & 1;
+    return f32(a * 8 + c * 4 + b * 2 + d) / 16.0 + 1.0 / 32.0;
+}
+
+// snaps to the closest palette colour, comparing in srgb since that's closer to how we see them
+fn quantize(color: vec4<f32>, pixel: vec2<i32>) -> vec4<f32> {
+    let wanted = linear_to_srgb(color.rgb) + (bayer(pixel) - 0.5) * data.dither;
+    var closest = vec3<f32>(0.0);
+    var closest_distance = 16.0;
+    for (var i = 0; i < i32(data.palette_size); i++) {
+        let entry = textureLoad(t_palette, vec2<i32>(i, 0), 0).rgb;
+        let offset = entry - wanted;
+        let distance = dot(offset, offset);
+        if (distance < closest_distance) {
+            closest = entry;
+            closest_distance = distance;
+        }
+    }
+    return vec4<f32>(srgb_to_linear(closest), color.a);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_texture, t_sampler, in.uv);
-}
\ No newline at end of file
+    let size = textureDimensions(t_texture);
+    let texel = in.uv * vec2<f32>(size);
+    // how many supersampled texels one screen pixel covers
+    let footprint = vec2<f32>(abs(dpdx(texel.x)), abs(dpdy(texel.y)));
+    let prec = f32(data.pixel_prec);
+
+    var color: vec4<f32>;
+    switch data.mode {
+        // nearest
+        case 0u: {
+            color = load_pixel(texel / prec, size);
+        }
+        // box
+        case 2u: {
+            let lo = texel - footprint * 0.5;
+            let hi = texel + footprint * 0.5;
+            let first = vec2<i32>(floor(lo));
+            // every texel the pixel touches, however big the supersampling factor is
+            let count = vec2<i32>(ceil(hi)) - first;
+            var sum = vec4<f32>(0.0);
+            var total = 0.0;
+            for (var y = 0; y < count.y; y++) {
+                let top = f32(first.y + y);
+                let h = min(hi.y, top + 1.0) - max(lo.y, top);
+                for (var x = 0; x < count.x; x++) {
+                    let left = f32(first.x + x);
+                    let w = min(hi.x, left + 1.0) - max(lo.x, left);
+                    sum += load(first + vec2<i32>(x, y), size) * w * h;
+                    total += w * h;
+                }
+            }
+            color = sum / total;
+        }
+        // sharp bilinear, which scales by the largest integer multiple that fits and then
+        // bilinearly filters the rest
+        case 3u: {
+            let pixel = texel / prec;
+            let scale = max(floor(prec / footprint), vec2<f32>(1.0));
+            let region = 0.5 - 0.5 / scale;
+            let center_distance = fract(pixel) - 0.5;
+            let f = (center_distance - clamp(center_distance, -region, region)) * scale + 0.5;
+            let sharp = floor(pixel) + f - 0.5;
+
+            let base = floor(sharp);
+            let t = sharp - base;
+            let top = mix(load_pixel(base, size), load_pixel(base + vec2<f32>(1.0, 0.0), size), t.x);
+            let bottom = mix(
+                load_pixel(base + vec2<f32>(0.0, 1.0), size),
+                load_pixel(base + vec2<f32>(1.0, 1.0), size),
+                t.x
+            );
+            color = mix(top, bottom, t.y);
+        }
+        // bilinear
+        default: {
+            color = textureSampleLevel(t_texture, t_sampler, in.uv, 0.0);
+        }
+    }
+
+    if (data.palette_size > 0u) {
+        // dither per base resolution pixel, so it looks like part of the art
+        color = quantize(color, vec2<i32>(floor(texel / prec)));
+    }
+    return color;
+}
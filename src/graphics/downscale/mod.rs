@@ -3,13 +3,142 @@ use winit::dpi::PhysicalSize;
 
 use self::vertex::Vertex;
 
+use super::pixel_art::texture::GPUTexture;
+use crate::file_system::{self, LoadError};
+
 mod vertex;
 
+/// How the supersampled pixel art texture is filtered down to the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DownscaleFilter {
+    /// Picks the closest base resolution pixel, which only looks right at integer scales.
+    Nearest,
+    /// A single bilinear sample of the supersampled texture.
+    #[default]
+    Bilinear,
+    /// Averages every supersampled texel covered by a screen pixel, weighted by coverage.
+    Box,
+    /// Scales by the largest integer multiple and bilinearly filters the remainder.
+    SharpBilinear,
+}
+
+impl DownscaleFilter {
+    pub const ALL: [DownscaleFilter; 4] = [
+        DownscaleFilter::Nearest,
+        DownscaleFilter::Bilinear,
+        DownscaleFilter::Box,
+        DownscaleFilter::SharpBilinear,
+    ];
+
+    pub fn as_int(&self) -> u32 {
+        match self {
+            DownscaleFilter::Nearest => 0,
+            DownscaleFilter::Bilinear => 1,
+            DownscaleFilter::Box => 2,
+            DownscaleFilter::SharpBilinear => 3,
+        }
+    }
+
+    /// The next filter, wrapping around, for cycling through them all.
+    pub fn next(&self) -> Self {
+        Self::ALL[(self.as_int() as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// How the camera is fit into a window with a different size or aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScalingMode {
+    /// The largest whole multiple of the base resolution that fits, so every pixel is the same size.
+    Integer,
+    /// Fits the whole camera in the window, keeping its aspect ratio.
+    #[default]
+    Fit,
+    /// Fills the whole window keeping the aspect ratio, cropping whatever doesn't fit.
+    Fill,
+    /// Fills the whole window, ignoring the aspect ratio.
+    Stretch,
+}
+
+/// What's drawn in the space the camera doesn't cover.
+#[derive(Debug)]
+pub enum Letterbox {
+    Color(wgpu::Color),
+    /// Stretched over the whole window, behind the camera.
+    Image(GPUTexture),
+}
+
+impl Default for Letterbox {
+    fn default() -> Self {
+        Letterbox::Color(wgpu::Color::BLACK)
+    }
+}
+
+/// Snaps every pixel drawn to the window to the closest colour in a palette, so blending from
+/// the downscale can't drift off palette.
+#[derive(Debug, Clone)]
+pub struct Quantization {
+    pub palette: Vec<[u8; 4]>,
+    /// How far, in 0 to 1 srgb units, a 4x4 ordered dither can push colours before they're
+    /// snapped. 0 turns dithering off.
+    pub dither: f32,
+}
+
+impl Quantization {
+    pub const MAX_COLORS: usize = 256;
+
+    /// Uses every distinct colour in an image, so swatch strips like famicube-32x.png work as is.
+    pub fn load(path: &str, dither: f32) -> Result<Self, LoadError> {
+        let img = file_system::load_image(path)?.to_rgba8();
+        let mut palette: Vec<[u8; 4]> = vec![];
+        for pixel in img.pixels() {
+            let color = [pixel[0], pixel[1], pixel[2], 255];
+            if !palette.contains(&color) {
+                palette.push(color);
+            }
+        }
+
+        if palette.len() > Self::MAX_COLORS {
+            return Err(LoadError::InvalidPalette(file_system::to_asset_path(path)));
+        }
+        Ok(Self { palette, dither })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DownscaleData {
+    pub mode: u32,
+    pub pixel_prec: u32,
+    /// 0 when not quantizing.
+    pub palette_size: u32,
+    pub dither: f32,
+}
+
 pub struct Downscale {
     pub vertex_buffer: wgpu::Buffer,
     pub shader: wgpu::ShaderModule,
     pub pipeline_layout: wgpu::PipelineLayout,
     pub render_pipeline: wgpu::RenderPipeline,
+
+    pub filter: DownscaleFilter,
+    pub pixel_prec: u32,
+    pub data_buffer: wgpu::Buffer,
+    pub data_bind_group_layout: wgpu::BindGroupLayout,
+    pub data_bind_group: wgpu::BindGroup,
+
+    pub quantization: Option<Quantization>,
+    /// The quantization palette as a single row, which isn't srgb so it's read as is.
+    pub palette_view: wgpu::TextureView,
+
+    pub scaling_mode: ScalingMode,
+    pub letterbox: Letterbox,
+    pub border_vertex_buffer: wgpu::Buffer,
+    pub border_data_buffer: wgpu::Buffer,
+    pub border_data_bind_group: wgpu::BindGroup,
+
+    /// Box filters the whole pixel art texture down to the base resolution, for capturing frames.
+    pub capture_data_buffer: wgpu::Buffer,
+    pub capture_data_bind_group: wgpu::BindGroup,
 }
 
 impl Downscale {
@@ -20,15 +149,58 @@ impl Downscale {
         config: &wgpu::SurfaceConfiguration,
         texture_bind_group_layout: &wgpu::BindGroupLayout,
         size: PhysicalSize<u32>,
+        pixel_prec: u32,
     ) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(include_str!("downscale.wgsl").into()),
         });
 
+        let filter = DownscaleFilter::default();
+        let data_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[Self::data(filter, pixel_prec, None)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let data_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let palette_view = device
+            .create_texture(&Self::palette_descriptor(1))
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let data_bind_group = Self::create_data_bind_group(
+            device,
+            &data_bind_group_layout,
+            &data_buffer,
+            &palette_view,
+        );
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&texture_bind_group_layout],
+            bind_group_layouts: &[texture_bind_group_layout, &data_bind_group_layout],
             push_constant_ranges: &[],
         });
 
@@ -70,22 +242,192 @@ impl Downscale {
             multiview: None,
         });
 
-        let vertices = Self::vertices(size.width, size.height, camera_width, camera_height);
+        let scaling_mode = ScalingMode::default();
+        let vertices = Self::vertices(
+            scaling_mode,
+            size.width,
+            size.height,
+            camera_width,
+            camera_height,
+        );
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
             contents: bytemuck::cast_slice(&vertices),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::VERTEX,
         });
 
+        // the border is stretched over the whole window, and is just a regular image
+        let border_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&Self::vertices(
+                ScalingMode::Stretch,
+                size.width,
+                size.height,
+                camera_width,
+                camera_height,
+            )),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let border_data_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[Self::data(DownscaleFilter::Nearest, 1, None)]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let border_data_bind_group = Self::create_data_bind_group(
+            device,
+            &data_bind_group_layout,
+            &border_data_buffer,
+            &palette_view,
+        );
+
+        // drawn with the border's vertices, which cover the whole target
+        let capture_data_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[Self::data(DownscaleFilter::Box, pixel_prec, None)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let capture_data_bind_group = Self::create_data_bind_group(
+            device,
+            &data_bind_group_layout,
+            &capture_data_buffer,
+            &palette_view,
+        );
+
         Self {
             vertex_buffer,
             shader,
             pipeline_layout,
             render_pipeline,
+            filter,
+            pixel_prec,
+            data_buffer,
+            data_bind_group_layout,
+            data_bind_group,
+            quantization: None,
+            palette_view,
+            scaling_mode,
+            letterbox: Letterbox::default(),
+            border_vertex_buffer,
+            border_data_buffer,
+            border_data_bind_group,
+            capture_data_buffer,
+            capture_data_bind_group,
         }
     }
 
+    fn data(
+        filter: DownscaleFilter,
+        pixel_prec: u32,
+        quantization: Option<&Quantization>,
+    ) -> DownscaleData {
+        DownscaleData {
+            mode: filter.as_int(),
+            pixel_prec,
+            palette_size: quantization.map_or(0, |q| q.palette.len() as u32),
+            dither: quantization.map_or(0.0, |q| q.dither),
+        }
+    }
+
+    fn write_data(&self, queue: &wgpu::Queue) {
+        let data = Self::data(self.filter, self.pixel_prec, self.quantization.as_ref());
+        queue.write_buffer(&self.data_buffer, 0, bytemuck::cast_slice(&[data]));
+        let capture = Self::data(DownscaleFilter::Box, self.pixel_prec, None);
+        queue.write_buffer(
+            &self.capture_data_buffer,
+            0,
+            bytemuck::cast_slice(&[capture]),
+        );
+    }
+
+    fn create_data_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        data_buffer: &wgpu::Buffer,
+        palette_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: data_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(palette_view),
+                },
+            ],
+        })
+    }
+
+    fn palette_descriptor(width: u32) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+
+    pub fn set_quantization(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        quantization: Option<Quantization>,
+    ) {
+        let quantization = quantization.filter(|q| !q.palette.is_empty());
+        if let Some(quantization) = &quantization {
+            let texture = device.create_texture_with_data(
+                queue,
+                &Self::palette_descriptor(quantization.palette.len() as u32),
+                bytemuck::cast_slice(&quantization.palette),
+            );
+            self.palette_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            // the border and captures are never quantized, but their bind groups need a palette
+            self.border_data_bind_group = Self::create_data_bind_group(
+                device,
+                &self.data_bind_group_layout,
+                &self.border_data_buffer,
+                &self.palette_view,
+            );
+            self.capture_data_bind_group = Self::create_data_bind_group(
+                device,
+                &self.data_bind_group_layout,
+                &self.capture_data_buffer,
+                &self.palette_view,
+            );
+            self.data_bind_group = Self::create_data_bind_group(
+                device,
+                &self.data_bind_group_layout,
+                &self.data_buffer,
+                &self.palette_view,
+            );
+        }
+        self.quantization = quantization;
+        self.write_data(queue);
+    }
+
+    pub fn set_filter(&mut self, queue: &wgpu::Queue, filter: DownscaleFilter) {
+        self.filter = filter;
+        self.write_data(queue);
+    }
+
+    /// Should match the pixel art texture's supersampling factor.
+    pub fn set_pixel_prec(&mut self, queue: &wgpu::Queue, pixel_prec: u32) {
+        self.pixel_prec = pixel_prec;
+        self.write_data(queue);
+    }
+
     pub fn vertices(
+        scaling_mode: ScalingMode,
         screen_width: u32,
         screen_height: u32,
         camera_width: u32,
@@ -95,34 +437,69 @@ impl Downscale {
         let (cw, ch) = (camera_width as f32, camera_height as f32);
         let width_r = sw / cw;
         let height_r = sh / ch;
-        let half_width = if width_r > height_r {
-            (sh / sw) * (cw / ch)
-        } else {
-            1.0
-        };
-        let half_height = if width_r < height_r {
-            (sw / sh) * (ch / cw)
-        } else {
-            1.0
+
+        // size of the camera on screen, in pixels
+        let (width, height) = match scaling_mode {
+            ScalingMode::Integer => {
+                let scale = width_r.min(height_r).floor().max(1.0);
+                (cw * scale, ch * scale)
+            }
+            ScalingMode::Fit => {
+                let scale = width_r.min(height_r);
+                (cw * scale, ch * scale)
+            }
+            ScalingMode::Fill => {
+                let scale = width_r.max(height_r);
+                (cw * scale, ch * scale)
+            }
+            ScalingMode::Stretch => (sw, sh),
         };
+        let (mut left, mut top) = ((sw - width) / 2.0, (sh - height) / 2.0);
+        if scaling_mode == ScalingMode::Integer {
+            // keep every pixel on a whole screen pixel
+            left = left.floor();
+            top = top.floor();
+        }
+
+        let x = |px: f32| px / sw * 2.0 - 1.0;
+        let y = |px: f32| 1.0 - px / sh * 2.0;
+        let (x0, x1) = (x(left), x(left + width));
+        let (y0, y1) = (y(top), y(top + height));
 
         [
             Vertex {
-                position: [-half_width, half_height, 0.0],
+                position: [x0, y0, 0.0],
                 uv: [0.0, 0.0],
             },
             Vertex {
-                position: [half_width, half_height, 0.0],
+                position: [x1, y0, 0.0],
                 uv: [1.0, 0.0],
             },
             Vertex {
-                position: [-half_width, -half_height, 0.0],
+                position: [x0, y1, 0.0],
                 uv: [0.0, 1.0],
             },
             Vertex {
-                position: [half_width, -half_height, 0.0],
+                position: [x1, y1, 0.0],
                 uv: [1.0, 1.0],
             },
         ]
     }
+
+    pub fn update_vertices(
+        &self,
+        queue: &wgpu::Queue,
+        size: PhysicalSize<u32>,
+        camera_width: u32,
+        camera_height: u32,
+    ) {
+        let vertices = Self::vertices(
+            self.scaling_mode,
+            size.width,
+            size.height,
+            camera_width,
+            camera_height,
+        );
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
 }
@@ -1,5 +1,9 @@
+pub mod assets;
 pub mod downscale;
 pub mod pixel_art;
+pub mod readback;
+pub mod recorder;
+pub mod software;
 
 /*  TODO:
 T   collision
@@ -9,7 +13,7 @@ T   make flipping instance based
 T   make animation frame instance based
 F   wasm
 F   alternating attacks
-F   palette swapping
+T   palette swapping
 F   hold after attack
 F   hitboxes
 F   hurtboxes
@@ -22,35 +26,44 @@ F   dashing
 F   allow specifying the size/rect of the sprite
 F   maybe add resources handles?
 */
+use image::RgbaImage;
 use itertools::iproduct;
 use std::{
     cell::RefCell,
-    collections::{HashMap, HashSet},
+    cmp::Ordering,
+    collections::HashSet,
+    f32::consts::TAU,
     iter,
+    ops::Range,
     rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
 };
-use wgpu::{util::DeviceExt, BindGroupEntry};
+use wgpu::util::DeviceExt;
 
 use crate::{
     file_system::{self, LoadError},
     graphics::pixel_art::{
+        atlas::{Atlas, AtlasPage, AtlasTexture},
         sprite::*,
-        sprite_sheet::*,
         texture::*,
-        tilemap::{ImageLayer, Tile},
     },
-    math::extend3d_to_uvec2,
+    math::{extend3d_to_uvec2, Rect},
 };
 use glam::*;
 use winit::{dpi::PhysicalSize, window::Window};
 
 use self::{
-    downscale::Downscale,
+    assets::AssetLoader,
+    downscale::{Downscale, DownscaleFilter, Letterbox, Quantization, ScalingMode},
     pixel_art::{
+        instance_buffer::InstanceBuffer,
+        palette::{Palette, PaletteHandle, Palettes, MAX_PALETTE_COLORS},
+        quad::QuadInstance,
         sprite_sheet::SpriteSheet,
-        tilemap::{TileInstance, TileLayer, TileMap},
+        tilemap::{TileLayer, TileMap},
         Camera, PixelArt,
     },
+    readback::Readback,
 };
 
 pub const TOP_LEFT: i32 = 0b1;
@@ -66,6 +79,48 @@ pub struct DrawParams {
     pub flip_x: bool,
     pub flip_y: bool,
     pub camera_locked: bool,
+    pub palette: Option<PaletteHandle>,
+    /// Multiplied with every colour drawn, in linear space.
+    pub tint: Vec3,
+    pub alpha: f32,
+    /// Linear colour the sprite is mixed towards, for hit flashes and the like.
+    pub flash_color: Vec3,
+    /// How far to mix towards `flash_color`, from 0 to 1.
+    pub flash: f32,
+    /// Around the sprite's origin.
+    pub rotation: Rotation,
+    /// Around the sprite's origin. Negative scales mirror the sprite.
+    pub scale: Vec2,
+}
+
+/// Clockwise rotation on screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rotation {
+    Radians(f32),
+    /// 90 degree steps, which keep every pixel intact.
+    QuarterTurns(i32),
+}
+
+impl Rotation {
+    pub fn radians(&self) -> f32 {
+        match self {
+            Rotation::Radians(r) => *r,
+            Rotation::QuarterTurns(n) => *n as f32 * std::f32::consts::FRAC_PI_2,
+        }
+    }
+
+    /// Cosine and sine, exact for quarter turns.
+    pub fn cos_sin(&self) -> Vec2 {
+        match self {
+            Rotation::Radians(r) => Vec2::new(r.cos(), r.sin()),
+            Rotation::QuarterTurns(n) => match n.rem_euclid(4) {
+                0 => Vec2::new(1.0, 0.0),
+                1 => Vec2::new(0.0, 1.0),
+                2 => Vec2::new(-1.0, 0.0),
+                _ => Vec2::new(0.0, -1.0),
+            },
+        }
+    }
 }
 
 pub fn new_flip_mask(flip_x: bool, flip_y: bool) -> i32 {
@@ -85,10 +140,123 @@ impl DrawParams {
         new_flip_mask(self.flip_x, self.flip_y)
     }
 
+    /// Top left corner of a quad of `size` drawn with these params, mirroring the origin when flipped.
+    fn offset(&self, size: Vec2, origin: Vec2) -> Vec2 {
+        let flipped_offset = self.position - size + origin;
+        let default_offset = self.position - origin;
+        Vec2::new(
+            if self.flip_x {
+                flipped_offset.x
+            } else {
+                default_offset.x
+            },
+            if self.flip_y {
+                flipped_offset.y
+            } else {
+                default_offset.y
+            },
+        )
+    }
+
     pub fn ui(mut self, enabled: bool) -> Self {
         self.camera_locked = enabled;
         self
     }
+
+    pub fn palette(mut self, palette: Option<PaletteHandle>) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    pub fn tint(mut self, tint: Vec3) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn alpha(mut self, alpha: f32) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn flash(mut self, color: Vec3, amount: f32) -> Self {
+        self.flash_color = color;
+        self.flash = amount;
+        self
+    }
+
+    pub fn rotate(mut self, radians: f32) -> Self {
+        self.rotation = Rotation::Radians(radians);
+        self
+    }
+
+    pub fn quarter_turns(mut self, turns: i32) -> Self {
+        self.rotation = Rotation::QuarterTurns(turns);
+        self
+    }
+
+    pub fn scale(mut self, scale: Vec2) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Swaps in the cached rotation closest to the one asked for, if there are any. Those are
+    /// already rotated, so the params that come back aren't. Sprites are scaled before they're
+    /// rotated, so the cache is only used when scaling afterwards would look the same, which is
+    /// when the scale is uniform.
+    fn prerotated<'a, T>(mut self, original: &'a T, rotations: &'a [T]) -> (&'a T, Self) {
+        let Rotation::Radians(radians) = self.rotation else {
+            return (original, self);
+        };
+        if rotations.is_empty() || self.scale.x != self.scale.y {
+            return (original, self);
+        }
+
+        // flipping and then rotating is the same as rotating the other way and then flipping
+        let radians = if self.flip_x != self.flip_y {
+            -radians
+        } else {
+            radians
+        };
+        let steps = rotations.len() as f32;
+        let i = (radians / TAU * steps).round().rem_euclid(steps) as usize;
+        self.rotation = Rotation::QuarterTurns(0);
+        (&rotations[i.min(rotations.len() - 1)], self)
+    }
+
+    /// Whether drawing `texture` with these params can leave partly transparent pixels.
+    fn is_translucent(&self, texture: &AtlasTexture, palettes: &[Palette]) -> bool {
+        let palette = self.palette.and_then(|p| palettes.get(p.0 as usize));
+        texture.translucent || self.alpha < 1.0 || palette.is_some_and(Palette::is_translucent)
+    }
+
+    /// Instance drawing a `size` quad with its top left corner at `offset`.
+    fn quad_instance(&self, offset: Vec2, depth: f32, size: Vec2, uv: Vec4) -> QuadInstance {
+        QuadInstance {
+            offset: [offset.x, offset.y, depth, 0.0],
+            flip_mask: self.to_flip_mask(),
+            camera_locked: self.camera_locked as i32,
+            palette: self.palette_index(),
+            tint: self.tint_array(),
+            flash: self.flash_array(),
+            pivot: (self.position - offset).to_array(),
+            scale: self.scale.to_array(),
+            rotation: self.rotation.cos_sin().to_array(),
+            uv: uv.to_array(),
+            size: size.to_array(),
+        }
+    }
+
+    fn palette_index(&self) -> i32 {
+        self.palette.map_or(-1, |p| p.0 as i32)
+    }
+
+    fn tint_array(&self) -> [f32; 4] {
+        self.tint.extend(self.alpha).to_array()
+    }
+
+    fn flash_array(&self) -> [f32; 4] {
+        self.flash_color.extend(self.flash).to_array()
+    }
 }
 
 pub enum DrawJob<'a> {
@@ -97,10 +265,67 @@ pub enum DrawJob<'a> {
     TileLayer(&'a TileLayer),
 }
 
-pub struct DrawQueue<'a>(pub Vec<DrawJob<'a>>);
+impl DrawJob<'_> {
+    /// What y-sorted layers sort by. Tile layers don't have a position, so they go behind
+    /// everything else on their layer.
+    fn y(&self) -> f32 {
+        match self {
+            DrawJob::Sprite(_, params) | DrawJob::SpriteSheet(_, _, params) => params.position.y,
+            DrawJob::TileLayer(_) => f32::NEG_INFINITY,
+        }
+    }
+}
+
+/// What a draw job is drawn on. Higher layers are drawn in front of lower ones, and jobs on the
+/// same layer in the order they were queued, unless the layer is y-sorted. Any number works, the
+/// named ones are just there to have something to put things in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Layer(pub i32);
+
+impl Layer {
+    pub const BACKGROUND: Layer = Layer(-100);
+    pub const WORLD: Layer = Layer(0);
+    pub const FOREGROUND: Layer = Layer(100);
+    pub const UI: Layer = Layer(200);
+}
+
+pub struct DrawQueue<'a> {
+    pub jobs: Vec<(Layer, DrawJob<'a>)>,
+    /// Layer new jobs go on.
+    layer: Layer,
+    y_sorted: HashSet<Layer>,
+}
+
+/// Everything translucent in a frame, sorted back to front. Neighbouring instances from the same
+/// atlas page are batched, and the ranges index into `instances`.
+struct Translucent<'a> {
+    batches: Vec<(&'a AtlasPage, Range<u32>)>,
+    instances: Vec<QuadInstance>,
+}
+
+impl<'a> Translucent<'a> {
+    fn new(mut jobs: Vec<(&'a AtlasPage, QuadInstance)>) -> Self {
+        // bigger depths are further back. The sort is stable, so a tile layer's tiles keep
+        // their order
+        jobs.sort_by(|(_, a), (_, b)| b.offset[2].total_cmp(&a.offset[2]));
+
+        let mut batches: Vec<(&AtlasPage, Range<u32>)> = vec![];
+        for (i, (page, _)) in jobs.iter().enumerate() {
+            let i = i as u32;
+            match batches.last_mut() {
+                Some((last, range)) if last.id == page.id => range.end = i + 1,
+                _ => batches.push((page, i..i + 1)),
+            }
+        }
+        Self {
+            batches,
+            instances: jobs.into_iter().map(|(_, instance)| instance).collect(),
+        }
+    }
+}
 
 pub struct Renderer {
-    surface: wgpu::Surface,
+    surface: Option<wgpu::Surface>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
@@ -114,8 +339,74 @@ pub struct Renderer {
 
     texture_bind_group_layout: wgpu::BindGroupLayout,
     sprite_ids: Rc<RefCell<HashSet<usize>>>,
+    /// Where every sprite and sprite sheet's image ends up.
+    atlas: RefCell<Atlas>,
 
+    /// Unit quad, scaled to the size of each sprite instance.
+    quad_vertex_buffer: wgpu::Buffer,
+    instance_buffer: RefCell<InstanceBuffer>,
     quad_index_buffer: wgpu::Buffer,
+
+    screenshot: Option<Screenshot>,
+    pixel_prec_override: Option<u32>,
+
+    /// The frame at the base resolution, read back a frame after it's drawn so capturing doesn't
+    /// wait on the GPU.
+    capture: Readback,
+    capture_next: bool,
+    captured: Option<RgbaImage>,
+    /// Where [`Renderer::render_to_image`] renders to, made the first time it's needed and again
+    /// whenever the size changes.
+    output: Option<Readback>,
+}
+
+/// A screenshot waiting for the next frame to be rendered.
+#[derive(Debug, Clone, Copy)]
+struct Screenshot {
+    logical: bool,
+}
+
+/// Averages every `factor` x `factor` block of pixels, blending in linear space.
+pub fn downsample(image: &RgbaImage, factor: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let linear: Vec<f32> = (0..=255).map(srgb_to_linear).collect();
+    RgbaImage::from_fn(width / factor, height / factor, |x, y| {
+        let mut sum = Vec4::ZERO;
+        for (i, j) in iproduct!(0..factor, 0..factor) {
+            let [r, g, b, a] = image.get_pixel(x * factor + i, y * factor + j).0;
+            sum += Vec4::new(
+                linear[r as usize],
+                linear[g as usize],
+                linear[b as usize],
+                a as f32 / 255.0,
+            );
+        }
+        let mean = sum / (factor * factor) as f32;
+        image::Rgba([
+            linear_to_srgb(mean.x),
+            linear_to_srgb(mean.y),
+            linear_to_srgb(mean.z),
+            (mean.w * 255.0).round() as u8,
+        ])
+    })
+}
+
+pub fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> u8 {
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
 }
 
 impl Default for DrawParams {
@@ -125,6 +416,13 @@ impl Default for DrawParams {
             flip_x: false,
             flip_y: false,
             camera_locked: false,
+            palette: None,
+            tint: Vec3::ONE,
+            alpha: 1.0,
+            flash_color: Vec3::ONE,
+            flash: 0.0,
+            rotation: Rotation::QuarterTurns(0),
+            scale: Vec2::ONE,
         }
     }
 }
@@ -150,174 +448,12 @@ impl Renderer {
 
     pub const INDICES: [u16; 6] = [2, 1, 0, 1, 2, 3];
 
-    pub fn load_tilemap(&self, path: &str) -> Result<TileMap, LoadError> {
-        use crate::graphics::pixel_art::tilemap::Vertex;
-
-        let path = &file_system::to_asset_path(path);
-        let mut loader = tiled::Loader::new();
-        let tmx = match loader.load_tmx_map(path) {
-            Ok(tile_map) => tile_map,
-            Err(_) => return Err(LoadError::PathNotFound(path.to_owned())),
-        };
-
-        let mut tile_layers = HashMap::new();
-        let mut image_layers = HashMap::new();
-
-        for layer in tmx.layers() {
-            match layer.layer_type() {
-                tiled::LayerType::TileLayer(tile_layer) => {
-                    let name = layer.name.clone();
-                    let tiles: Vec<Tile> = iproduct!(
-                        0..tile_layer.width().unwrap(),
-                        0..tile_layer.height().unwrap()
-                    )
-                    .filter_map(|(x, y)| {
-                        tile_layer.get_tile(x as i32, y as i32).map(|t| Tile {
-                            flip_x: t.flip_h,
-                            flip_y: t.flip_v,
-                            id: t.id(),
-                            x: x as i32,
-                            y: y as i32,
-                        })
-                    })
-                    .collect();
-
-                    let coords = iproduct!(
-                        0..tile_layer.width().unwrap(),
-                        0..tile_layer.height().unwrap()
-                    );
-
-                    //get first used tile
-                    //TODO: find some way to short circuit this
-                    let (x, y) = coords
-                        .skip_while(|(x, y)| tile_layer.get_tile(*x as i32, *y as i32).is_none())
-                        .nth(0)
-                        .unwrap();
-
-                    let texture = tile_layer
-                        .get_tile(x as i32, y as i32)
-                        .as_ref()
-                        .unwrap()
-                        .get_tileset()
-                        .image
-                        .as_ref()
-                        .unwrap()
-                        .source
-                        .to_str()
-                        .unwrap()["./assets".len()..]
-                        .to_owned();
-
-                    let mut instances: Vec<TileInstance> = vec![];
-                    let mut instance_count = 0;
-
-                    for tile in tiles.iter() {
-                        instances.push(TileInstance {
-                            offset: [
-                                (tmx.tile_width as i32 * tile.x) as f32,
-                                (tmx.tile_height as i32 * tile.y) as f32,
-                            ],
-                            flip_mask: new_flip_mask(tile.flip_x, tile.flip_y),
-                            tile_index: tile.id as i32,
-                        });
-                        instance_count += 1;
-                    }
-
-                    let instance_buffer =
-                        self.device
-                            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                label: None,
-                                contents: bytemuck::cast_slice(&instances),
-                                usage: wgpu::BufferUsages::VERTEX,
-                            });
-
-                    let vertex_buffer =
-                        self.device
-                            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                label: None,
-                                contents: bytemuck::cast_slice(&[
-                                    Vertex {
-                                        position: [0.0, 0.0],
-                                        uv_mask: TOP_LEFT,
-                                    },
-                                    Vertex {
-                                        position: [tmx.tile_width as f32, 0.0],
-                                        uv_mask: TOP_RIGHT,
-                                    },
-                                    Vertex {
-                                        position: [0.0, tmx.tile_height as f32],
-                                        uv_mask: BOTTOM_LEFT,
-                                    },
-                                    Vertex {
-                                        position: [tmx.tile_width as f32, tmx.tile_height as f32],
-                                        uv_mask: BOTTOM_RIGHT,
-                                    },
-                                ]),
-                                usage: wgpu::BufferUsages::VERTEX,
-                            });
-
-                    let tile_depth_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-                        label: None,
-                        size: std::mem::size_of::<f32>() as u64,
-                        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
-                        mapped_at_creation: false,
-                    });
-                    let tile_depth_bind_group =
-                        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                            label: None,
-                            layout: &self.pixel_art.tile_depth_bind_group_layout,
-                            entries: &[wgpu::BindGroupEntry {
-                                binding: 0,
-                                resource: tile_depth_buffer.as_entire_binding(),
-                            }],
-                        });
-
-                    tile_layers.insert(
-                        name.clone(),
-                        TileLayer {
-                            name: name.clone(),
-                            texture: self.load_texture(&texture).unwrap(),
-                            vertex_buffer,
-                            instance_buffer,
-                            instance_count,
-                            tile_depth_buffer,
-                            tile_depth_bind_group,
-                            tiles,
-                        },
-                    );
-                }
-                tiled::LayerType::ImageLayer(image_layer) => {
-                    let sprite = self.load_sprite(
-                        Origin::TopLeft,
-                        &image_layer.image.as_ref().unwrap().source.to_str().unwrap()
-                            ["./assets".len()..],
-                    )?;
-
-                    image_layers.insert(
-                        layer.name.clone(),
-                        ImageLayer {
-                            sprite,
-                            repeat_x: match layer.properties.get("Repeat X") {
-                                Some(tiled::PropertyValue::BoolValue(b)) => *b,
-                                _ => false,
-                            },
-                            repeat_y: match layer.properties.get("Repeat Y") {
-                                Some(tiled::PropertyValue::BoolValue(b)) => *b,
-                                _ => false,
-                            },
-                            position: Vec2::new(layer.offset_x, layer.offset_y),
-                        },
-                    );
-                }
-                _ => {}
-            }
-        }
+    pub fn downscale_filter(&self) -> DownscaleFilter {
+        self.downscaling.filter
+    }
 
-        Ok(TileMap {
-            tile_layers,
-            image_layers,
-            tile_width: tmx.tile_width,
-            tile_height: tmx.tile_height,
-        })
+    pub fn set_downscale_filter(&mut self, filter: DownscaleFilter) {
+        self.downscaling.set_filter(&self.queue, filter);
     }
 
     pub fn load_texture(&self, path: &str) -> Result<GPUTexture, LoadError> {
@@ -338,6 +474,11 @@ impl Renderer {
             };
             top
         };
+        Ok(self.create_texture(rgba))
+    }
+
+    /// Uploads an image as a texture of its own, outside the atlas.
+    pub fn create_texture(&self, rgba: RgbaImage) -> GPUTexture {
         let dimensions = rgba.dimensions();
 
         let size = wgpu::Extent3d {
@@ -380,144 +521,61 @@ impl Renderer {
             ],
         });
 
-        Ok(GPUTexture {
+        GPUTexture {
             bind_group,
             texture,
             view,
             size: extend3d_to_uvec2(&size),
             sampler,
-        })
+        }
     }
 
-    pub fn load_sprite(&self, origin: Origin, path: &str) -> Result<Sprite, LoadError> {
-        let texture = self.load_texture(path)?;
-
-        let (width, height) = texture.size.as_vec2().into();
-
-        let vertex_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&pixel_art::sprite::Vertex::from_size(
-                    width, height,
-                )),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-
-        let ids = self.sprite_ids.clone();
-        let id = {
-            let mut id = 0;
-            loop {
-                if !ids.as_ref().borrow().contains(&id) {
-                    ids.borrow_mut().insert(id);
-                    break id;
-                }
-                id += 1;
-            }
-        };
+    /// Loads every palette in an image. The first row lists the colours to swap, and every row
+    /// below it is a palette they get swapped with.
+    pub fn load_palettes(&mut self, path: &str) -> Result<Vec<PaletteHandle>, LoadError> {
+        let img = file_system::load_image(path)?.to_rgba8();
+        if img.width() as usize > MAX_PALETTE_COLORS || img.height() < 2 {
+            return Err(LoadError::InvalidPalette(file_system::to_asset_path(path)));
+        }
 
-        Ok(Sprite {
-            vertex_buffer,
-            texture,
-            origin,
-            id,
-            ids,
-            instances: RefCell::new(vec![]),
-        })
-    }
-
-    pub fn load_sprite_sheet(
-        &self,
-        origin: Origin,
-        path: &str,
-        count: u8,
-        frame_rate: FrameRate,
-        orientation: Orientation,
-    ) -> Result<SpriteSheet, LoadError> {
-        let texture = self.load_texture(path)?;
-        let ids = self.sprite_ids.clone();
-        let id = {
-            let mut id = 0;
-            loop {
-                if !ids.as_ref().borrow().contains(&id) {
-                    ids.borrow_mut().insert(id);
-                    break id;
-                }
-                id += 1;
-            }
-        };
+        let row = |y| (0..img.width()).map(|x| img.get_pixel(x, y).0).collect();
+        let from: Vec<[u8; 4]> = row(0);
+        (1..img.height())
+            .map(|y| self.create_palette(from.clone(), row(y)))
+            .collect()
+    }
 
-        use pixel_art::sprite_sheet::Vertex;
-        let (width, height) = (texture.size.as_vec2()
-            / match orientation {
-                Orientation::Vertical => Vec2::new(1.0, count as f32),
-                Orientation::Horizontal => Vec2::new(count as f32, 1.0),
-            })
-        .into();
-        let vertex_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&[
-                    Vertex {
-                        position: [0.0, 0.0],
-                        uv_mask: TOP_LEFT,
-                    },
-                    Vertex {
-                        position: [width, 0.0],
-                        uv_mask: TOP_RIGHT,
-                    },
-                    Vertex {
-                        position: [0.0, height],
-                        uv_mask: BOTTOM_LEFT,
-                    },
-                    Vertex {
-                        position: [width, height],
-                        uv_mask: BOTTOM_RIGHT,
-                    },
-                ]),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
+    /// Makes a palette that draws each colour in `from` as the colour at the same index in `to`.
+    /// Fails if they aren't the same length, or have more than [`MAX_PALETTE_COLORS`] colours.
+    pub fn create_palette(
+        &mut self,
+        from: Vec<[u8; 4]>,
+        to: Vec<[u8; 4]>,
+    ) -> Result<PaletteHandle, LoadError> {
+        if from.len() > MAX_PALETTE_COLORS || from.len() != to.len() {
+            return Err(LoadError::InvalidPalette(format!(
+                "{} colours swapped for {}, at most {} allowed",
+                from.len(),
+                to.len(),
+                MAX_PALETTE_COLORS
+            )));
+        }
 
-        let data_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&[SpriteSheetData {
-                    count: count as i32,
-                    horizontal: orientation.as_int(),
-                }]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::VERTEX,
-            });
-        let data_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &self.pixel_art.sprite_sheet_data_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: data_buffer.as_entire_binding(),
-            }],
-        });
+        Ok(self
+            .pixel_art
+            .add_palette(&self.device, &self.queue, Palette { from, to }))
+    }
 
-        Ok(SpriteSheet {
-            count,
-            frame_rate,
-            orientation,
-            id,
-            ids,
-            texture,
-            origin,
-            vertex_buffer,
-            instances: RefCell::new(vec![]),
-            data_buffer,
-            data_bind_group,
-        })
+    /// Palettes in the order they were created, for drawing the same frames in software.
+    pub fn palettes(&self) -> &Palettes {
+        &self.pixel_art.palettes
     }
 
     pub async fn new(window: &Window, camera_width: u32, camera_height: u32) -> Renderer {
-        let size = window.inner_size().clone();
+        let size = window.inner_size();
 
         // ------------------------------------------------------------------------------------------- Wgpu Initialization
-        let (_, surface, _, device, queue, config) = {
+        let (surface, device, queue, config) = {
             // The instance is a handle to our GPU
             // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
             let instance = wgpu::Instance::new(wgpu::Backends::all());
@@ -530,23 +588,7 @@ impl Renderer {
                 })
                 .await
                 .unwrap();
-            let (device, queue) = adapter
-                .request_device(
-                    &wgpu::DeviceDescriptor {
-                        label: None,
-                        features: wgpu::Features::empty(),
-                        // WebGL doesn't support all of wgpu's features, so if
-                        // we're building for the web we'll have to disable some.
-                        limits: if cfg!(target_arch = "wasm32") {
-                            wgpu::Limits::downlevel_webgl2_defaults()
-                        } else {
-                            wgpu::Limits::default()
-                        },
-                    },
-                    None, // Trace path
-                )
-                .await
-                .unwrap();
+            let (device, queue) = Self::request_device(&adapter).await;
 
             let config = wgpu::SurfaceConfiguration {
                 usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -558,9 +600,89 @@ impl Renderer {
             };
             surface.configure(&device, &config);
 
-            (instance, surface, adapter, device, queue, config)
+            (surface, device, queue, config)
+        };
+
+        Self::from_device(
+            Some(surface),
+            device,
+            queue,
+            config,
+            size,
+            camera_width,
+            camera_height,
+        )
+    }
+
+    /// Creates a renderer without a window. Frames can only be read back with
+    /// [`Renderer::render_to_image`], and `None` is returned if there is no adapter to render with.
+    pub async fn new_headless(
+        camera_width: u32,
+        camera_height: u32,
+        size: PhysicalSize<u32>,
+    ) -> Option<Renderer> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await?;
+        let (device, queue) = Self::request_device(&adapter).await;
+
+        // there's no surface to configure, but the pipelines still need to know the output format
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: Self::HEADLESS_FORMAT,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
         };
 
+        Some(Self::from_device(
+            None,
+            device,
+            queue,
+            config,
+            size,
+            camera_width,
+            camera_height,
+        ))
+    }
+
+    const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+    async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    // WebGL doesn't support all of wgpu's features, so if
+                    // we're building for the web we'll have to disable some.
+                    limits: if cfg!(target_arch = "wasm32") {
+                        wgpu::Limits::downlevel_webgl2_defaults()
+                    } else {
+                        wgpu::Limits::default()
+                    },
+                },
+                None, // Trace path
+            )
+            .await
+            .unwrap()
+    }
+
+    fn from_device(
+        surface: Option<wgpu::Surface>,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        size: PhysicalSize<u32>,
+        camera_width: u32,
+        camera_height: u32,
+    ) -> Renderer {
         // ------------------------------------------------------------------------------------------- Texture Bind Group
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -585,6 +707,14 @@ impl Renderer {
                 label: None,
             });
 
+        let pixel_prec = PixelArt::pixel_prec_for(
+            &device,
+            size.width,
+            size.height,
+            camera_width,
+            camera_height,
+        );
+
         // ------------------------------------------------------------------------------------------- Downscaling
         let downscaling = Downscale::new(
             &device,
@@ -593,6 +723,7 @@ impl Renderer {
             &config,
             &texture_bind_group_layout,
             size,
+            pixel_prec,
         );
 
         // ------------------------------------------------------------------------------------------- Upscaling
@@ -602,6 +733,7 @@ impl Renderer {
             camera_height,
             &config,
             &texture_bind_group_layout,
+            pixel_prec,
         );
 
         // ------------------------------------------------------------------------------------------- Sprites
@@ -610,6 +742,14 @@ impl Renderer {
             contents: bytemuck::cast_slice(&Self::INDICES),
             usage: wgpu::BufferUsages::INDEX,
         });
+        let quad_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&pixel_art::quad::Vertex::from_size(1.0, 1.0)),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let atlas = RefCell::new(Atlas::new(&device));
+        let instance_buffer = RefCell::new(InstanceBuffer::new(&device));
+        let capture = Readback::new(&device, camera_width, camera_height, config.format);
 
         Self {
             surface,
@@ -621,13 +761,24 @@ impl Renderer {
             downscaling,
             pixel_art,
 
+            quad_vertex_buffer,
+            instance_buffer,
             quad_index_buffer,
 
             texture_bind_group_layout,
             sprite_ids: Rc::new(RefCell::new(HashSet::new())),
+            atlas,
 
             camera_width,
             camera_height,
+
+            screenshot: None,
+            pixel_prec_override: None,
+
+            capture,
+            capture_next: false,
+            captured: None,
+            output: None,
         }
     }
 
@@ -636,151 +787,344 @@ impl Renderer {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            self.output = None;
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
 
-            self.pixel_art.depth_texture = TextureRaw::create_depth_texture(
-                &self.device,
+            self.update_pixel_prec();
+
+            self.downscaling.update_vertices(
+                &self.queue,
+                self.size,
                 self.camera_width,
                 self.camera_height,
             );
+        }
+    }
+
+    /// Fixes the supersampling factor, or with `None` picks it from the window size. It's clamped
+    /// to what the device can make a texture for.
+    pub fn set_pixel_prec(&mut self, pixel_prec: Option<u32>) {
+        let max_prec =
+            PixelArt::max_pixel_prec(&self.device, self.camera_width, self.camera_height);
+        self.pixel_prec_override = pixel_prec.map(|prec| prec.clamp(1, max_prec));
+        self.update_pixel_prec();
+    }
+
+    pub fn pixel_prec(&self) -> u32 {
+        self.pixel_art.pixel_prec
+    }
 
-            let vertices = Downscale::vertices(
+    /// Recreates the render and depth textures at the supersampling factor that should be used.
+    fn update_pixel_prec(&mut self) {
+        let pixel_prec = self.pixel_prec_override.unwrap_or_else(|| {
+            PixelArt::pixel_prec_for(
+                &self.device,
                 self.size.width,
                 self.size.height,
                 self.camera_width,
                 self.camera_height,
-            );
+            )
+        });
 
-            self.queue.write_buffer(
-                &self.downscaling.vertex_buffer,
-                0,
-                bytemuck::cast_slice(&vertices),
+        if pixel_prec != self.pixel_art.pixel_prec {
+            self.pixel_art.pixel_prec = pixel_prec;
+            self.pixel_art.texture = PixelArt::create_texture(
+                &self.device,
+                self.camera_width,
+                self.camera_height,
+                pixel_prec,
+                &self.texture_bind_group_layout,
             );
+            self.downscaling.set_pixel_prec(&self.queue, pixel_prec);
         }
+
+        self.pixel_art.depth_texture = TextureRaw::create_depth_texture(
+            &self.device,
+            self.camera_width,
+            self.camera_height,
+            pixel_prec,
+        );
     }
 
-    fn flatten_draw_queue<'a>(
-        &self,
-        mut dq: DrawQueue<'a>,
-    ) -> (Vec<&'a Sprite>, Vec<&'a SpriteSheet>, Vec<&'a TileLayer>) {
-        let mut sprites: Vec<&Sprite> = vec![];
-        let mut sprite_ids = HashSet::new();
-        let mut sprite_sheets: Vec<&SpriteSheet> = vec![];
-        let mut sprite_sheet_ids = HashSet::new();
-        let mut tile_layers: Vec<&TileLayer> = vec![];
+    pub fn set_scaling_mode(&mut self, scaling_mode: ScalingMode) {
+        self.downscaling.scaling_mode = scaling_mode;
+        self.downscaling.update_vertices(
+            &self.queue,
+            self.size,
+            self.camera_width,
+            self.camera_height,
+        );
+    }
+
+    pub fn set_letterbox(&mut self, letterbox: Letterbox) {
+        self.downscaling.letterbox = letterbox;
+    }
+
+    /// Snaps the final image to a palette, or stops doing so with `None`. Can be changed at any
+    /// time, e.g. when switching scenes.
+    pub fn set_quantization(&mut self, quantization: Option<Quantization>) {
+        self.downscaling
+            .set_quantization(&self.device, &self.queue, quantization);
+    }
+
+    /// Turns the draw queue into quad instances. Opaque ones are left in the atlas page they're
+    /// drawn from, and translucent ones are returned to be drawn afterwards.
+    fn flatten_draw_queue<'a>(&self, dq: DrawQueue<'a>) -> (Vec<&'a AtlasPage>, Translucent<'a>) {
+        let palettes = &self.pixel_art.palettes.palettes;
+        let mut pages: Vec<&AtlasPage> = vec![];
+        let mut page_ids = HashSet::new();
+        let mut translucent = vec![];
+        let mut push =
+            |page: Option<&'a AtlasPage>, instance: QuadInstance, is_translucent: bool| {
+                // textures without a page were only loaded for the software renderer
+                let Some(page) = page else {
+                    return;
+                };
+                if is_translucent {
+                    translucent.push((page, instance));
+                } else {
+                    page.instances.borrow_mut().push(instance);
+                    if page_ids.insert(page.id) {
+                        pages.push(page);
+                    }
+                }
+            };
+
+        // tile layers only draw the chunks the camera can see
+        let view = Rect {
+            x: self.pixel_art.camera.offset().x,
+            y: self.pixel_art.camera.offset().y,
+            w: self.camera_width as f32,
+            h: self.camera_height as f32,
+        };
+
         let mut depth = 1.0;
-        let depth_step = 1.0 / (dq.0.len() as f32);
-        for job in dq.0.iter_mut() {
+        let jobs = dq.into_sorted();
+        let depth_step = 1.0 / (jobs.len() as f32);
+        for job in jobs.iter() {
             match job {
                 DrawJob::Sprite(sprite, params) => {
-                    let origin = sprite.origin();
+                    let (sprite, params) = params.prerotated(*sprite, &sprite.rotations);
                     let size = sprite.texture.size.as_vec2();
-
-                    // flip logic
-                    let flipped_offset = params.position - size + origin;
-                    let default_offset = params.position - origin;
-                    let offset = if params.flip_x && params.flip_y {
-                        flipped_offset
-                    } else if params.flip_x {
-                        Vec2::new(flipped_offset.x, default_offset.y)
-                    } else if params.flip_y {
-                        Vec2::new(default_offset.x, flipped_offset.y)
-                    } else {
-                        default_offset
-                    };
-                    let instance = SpriteInstance {
-                        offset: [offset.x, offset.y, depth, 0.0],
-                        flip_mask: params.to_flip_mask(),
-                        ui: params.camera_locked as i32,
-                    };
-
-                    sprite.instances.borrow_mut().push(instance);
-                    if !sprite_ids.contains(&sprite.id) {
-                        sprites.push(sprite);
-                        sprite_ids.insert(sprite.id);
-                    }
+                    let offset = params.offset(size, sprite.origin());
+                    push(
+                        sprite.texture.page.as_deref(),
+                        params.quad_instance(offset, depth, size, sprite.texture.uv),
+                        params.is_translucent(&sprite.texture, palettes),
+                    );
                 }
                 DrawJob::SpriteSheet(sprite_sheet, t, params) => {
-                    let origin = sprite_sheet.origin();
+                    let (sprite_sheet, params) =
+                        params.prerotated(*sprite_sheet, &sprite_sheet.rotations);
                     let size = sprite_sheet.size().as_vec2();
-
-                    let fo = params.position - size + origin;
-                    let defo = params.position - origin;
-                    let offset = if params.flip_x && params.flip_y {
-                        fo
-                    } else if params.flip_x {
-                        Vec2::new(fo.x, defo.y)
-                    } else if params.flip_y {
-                        Vec2::new(defo.x, fo.y)
-                    } else {
-                        defo
-                    };
-
-                    let instance = SpriteSheetInstance {
-                        offset: [offset.x, offset.y, depth, 0.0],
-                        flip_mask: new_flip_mask(params.flip_x, params.flip_y),
-                        t: *t as i32,
-                    };
-
-                    sprite_sheet.instances.borrow_mut().push(instance);
-                    if !sprite_sheet_ids.contains(&sprite_sheet.id) {
-                        sprite_sheets.push(sprite_sheet);
-                        sprite_sheet_ids.insert(sprite_sheet.id);
-                    }
+                    let offset = params.offset(size, sprite_sheet.origin());
+                    push(
+                        sprite_sheet.texture.page.as_deref(),
+                        params.quad_instance(offset, depth, size, sprite_sheet.frame_uv(*t)),
+                        params.is_translucent(&sprite_sheet.texture, palettes),
+                    );
                 }
                 DrawJob::TileLayer(tile_layer) => {
-                    self.queue.write_buffer(
-                        &tile_layer.tile_depth_buffer,
-                        0,
-                        bytemuck::cast_slice(&[depth]),
-                    );
-                    tile_layers.push(tile_layer);
+                    let visible = tile_layer
+                        .chunks
+                        .iter()
+                        .filter(|chunk| chunk.bounds.contains(&view));
+                    for chunk in visible {
+                        for (tile, instance) in chunk.tiles.iter().zip(&chunk.instances) {
+                            let tile = &tile_layer.tiles[*tile];
+                            let texture = &tile_layer.tilesets[tile.tileset].texture;
+                            let mut instance = *instance;
+                            instance.offset[2] = depth;
+                            push(texture.page.as_deref(), instance, texture.translucent);
+                        }
+                    }
                 }
             }
 
             depth -= depth_step;
         }
 
-        (sprites, sprite_sheets, tile_layers)
+        (pages, Translucent::new(translucent))
     }
 
     pub fn render(&mut self, dq: DrawQueue) {
-        let (sprites, sprite_sheets, tile_layers) = self.flatten_draw_queue(dq);
-
         // Setup rendering
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        let sprite_iter: Vec<(&Sprite, wgpu::Buffer)> = sprites
-            .into_iter()
-            .map(|spr| {
-                (
-                    spr,
-                    self.device
-                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: None,
-                            contents: bytemuck::cast_slice(&spr.instances.borrow()),
-                            usage: wgpu::BufferUsages::VERTEX,
-                        }),
-                )
-            })
-            .collect();
+        self.draw_pixel_art(&mut encoder, dq);
+
+        // Render To Screen
+        let screen = self
+            .surface
+            .as_ref()
+            .expect("headless renderers can only render_to_image")
+            .get_current_texture()
+            .unwrap();
+        let view = screen
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.draw_downscale(&mut encoder, &view);
+
+        // surfaces can't always be copied from, so the same frame is drawn again to a texture that can
+        let screenshot = self.screenshot.take().map(|screenshot| {
+            self.create_output();
+            let output = self.output.as_ref().unwrap();
+            self.draw_downscale(&mut encoder, &output.view);
+            output.copy(&mut encoder);
+
+            let size = self.pixel_art.texture.size;
+            let pixel_art = Readback::new(&self.device, size.x, size.y, PixelArt::TEXTURE_FORMAT);
+            pixel_art.copy_from(&mut encoder, &self.pixel_art.texture.texture);
+            (screenshot, pixel_art)
+        });
+
+        self.submit_with_capture(encoder);
+
+        if let Some((screenshot, pixel_art)) = screenshot {
+            self.save_screenshot(screenshot, pixel_art);
+        }
+
+        screen.present();
+    }
+
+    /// Renders the draw queue into an offscreen texture of `out_size` and reads the result back.
+    /// The renderer is resized to `out_size` if it isn't already that size.
+    pub fn render_to_image(&mut self, dq: DrawQueue, out_size: PhysicalSize<u32>) -> RgbaImage {
+        if out_size != self.size {
+            self.resize(out_size);
+        }
 
-        let sprite_sheet_iter: Vec<(&SpriteSheet, wgpu::Buffer)> = sprite_sheets
+        self.create_output();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        self.draw_pixel_art(&mut encoder, dq);
+
+        let output = self.output.as_ref().unwrap();
+        self.draw_downscale(&mut encoder, &output.view);
+        output.copy(&mut encoder);
+
+        self.submit_with_capture(encoder);
+
+        let output = self.output.as_mut().unwrap();
+        output.map();
+        output.read(&self.device).unwrap()
+    }
+
+    /// Saves the next rendered frame to the screenshots directory, both at window resolution and
+    /// as the supersampled pixel art target. `logical` also saves the frame at the base resolution.
+    pub fn screenshot(&mut self, logical: bool) {
+        self.screenshot = Some(Screenshot { logical });
+    }
+
+    /// Reads back the output and `pixel_art` once the frame they were copied in is submitted, and
+    /// saves them.
+    fn save_screenshot(&mut self, screenshot: Screenshot, mut pixel_art: Readback) {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        let output = self.output.as_mut().unwrap();
+        output.map();
+        let window = output.read(&self.device).unwrap();
+        pixel_art.map();
+        let pixel_art = pixel_art.read(&self.device).unwrap();
+
+        let mut images = vec![("window", window)];
+        if screenshot.logical {
+            images.push(("logical", downsample(&pixel_art, self.pixel_art.pixel_prec)));
+        }
+        images.push(("pixel_art", pixel_art));
+
+        for (name, image) in images {
+            let path = file_system::to_screenshot_path(&format!("{}_{}.png", stamp, name));
+            match file_system::save_image(&path, &image) {
+                Ok(()) => log::info!("saved screenshot to {}", path),
+                Err(e) => log::warn!("couldn't save screenshot: {:?}", e),
+            }
+        }
+    }
+
+    /// How many instances fit in the instance buffer before it has to be reallocated.
+    pub fn instance_capacity(&self) -> u64 {
+        self.instance_buffer.borrow().capacity
+    }
+
+    /// Box filters the next rendered frame down to the base resolution on the GPU, to be picked
+    /// up with [`Renderer::take_captured_frame`] once it's been read back.
+    pub fn capture_next_frame(&mut self) {
+        self.capture_next = true;
+    }
+
+    /// The last captured frame, which becomes available the frame after it was rendered.
+    pub fn take_captured_frame(&mut self) -> Option<RgbaImage> {
+        self.captured.take()
+    }
+
+    /// Submits a frame, capturing it too if that was asked for.
+    fn submit_with_capture(&mut self, mut encoder: wgpu::CommandEncoder) {
+        // the last frame's capture has to be read before its buffer is copied to again. By now the
+        // GPU has long finished with it, so this shouldn't have to wait
+        if self.capture.is_mapping() {
+            self.captured = self.capture.read(&self.device);
+        }
+
+        if self.capture_next {
+            self.draw_capture(&mut encoder);
+            self.capture.copy(&mut encoder);
+        }
+
+        self.queue.submit(iter::once(encoder.finish()));
+
+        if self.capture_next {
+            self.capture.map();
+            self.capture_next = false;
+        }
+    }
+
+    /// Makes the texture [`Renderer::render_to_image`] and screenshots render to, if there isn't
+    /// one at the current size yet.
+    fn create_output(&mut self) {
+        if self.output.is_none() {
+            self.output = Some(Readback::new(
+                &self.device,
+                self.size.width,
+                self.size.height,
+                self.config.format,
+            ));
+        }
+    }
+
+    fn draw_pixel_art(&self, encoder: &mut wgpu::CommandEncoder, dq: DrawQueue) {
+        let (pages, translucent) = self.flatten_draw_queue(dq);
+
+        // every page's opaque instances, followed by the translucent ones, all go in one buffer
+        let mut instance_buffer = self.instance_buffer.borrow_mut();
+        instance_buffer.instances.clear();
+        let page_ranges: Vec<(&AtlasPage, Range<u32>)> = pages
             .into_iter()
-            .map(|spr| {
-                (
-                    spr,
-                    self.device
-                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: None,
-                            contents: bytemuck::cast_slice(&spr.instances.borrow()),
-                            usage: wgpu::BufferUsages::VERTEX,
-                        }),
-                )
+            .map(|page| {
+                let start = instance_buffer.instances.len() as u32;
+                // cleared rather than moved out, so the page keeps its allocation for next frame
+                let mut instances = page.instances.borrow_mut();
+                instance_buffer.instances.extend_from_slice(&instances);
+                instances.clear();
+                (page, start..instance_buffer.instances.len() as u32)
             })
             .collect();
+        let translucent_start = instance_buffer.instances.len() as u32;
+        instance_buffer
+            .instances
+            .extend_from_slice(&translucent.instances);
+        instance_buffer.upload(&self.device, &self.queue);
 
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
@@ -810,79 +1154,78 @@ impl Renderer {
         render_pass.set_bind_group(1, &self.pixel_art.w2p.bind_group, &[]);
         render_pass.set_bind_group(2, &self.pixel_art.camera_bind_group, &[]);
 
-        // Render Sprites
-        render_pass.set_pipeline(&self.pixel_art.sprite_render_pipeline);
+        render_pass.set_vertex_buffer(0, self.quad_vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instance_buffer.buffer.slice(..));
+        render_pass.set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
 
-        for (sprite, instance_buffer) in sprite_iter.iter() {
-            render_pass.set_bind_group(0, &sprite.texture.bind_group, &[]);
-            render_pass.set_vertex_buffer(0, sprite.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-            render_pass
-                .set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(
-                0..Self::INDICES.len() as u32,
-                0,
-                0..sprite.instances.borrow().len() as u32,
-            );
+        // Render everything opaque, a page at a time
+        render_pass.set_pipeline(&self.pixel_art.quad_render_pipeline);
 
-            sprite.instances.borrow_mut().clear();
+        for (page, range) in page_ranges {
+            render_pass.set_bind_group(0, &page.bind_group, &[]);
+            render_pass.draw_indexed(0..Self::INDICES.len() as u32, 0, range);
         }
 
-        // Render Tile Layers
-        render_pass.set_pipeline(&self.pixel_art.tile_layer_render_pipeline);
+        // Render everything translucent, back to front
+        render_pass.set_pipeline(&self.pixel_art.quad_translucent_pipeline);
 
-        for tile_layer in tile_layers.iter() {
-            render_pass.set_bind_group(3, &tile_layer.tile_depth_bind_group, &[]);
-            render_pass.set_bind_group(0, &tile_layer.texture.bind_group, &[]);
-            render_pass.set_vertex_buffer(0, tile_layer.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, tile_layer.instance_buffer.slice(..));
-            render_pass
-                .set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        for (page, range) in translucent.batches.iter() {
+            render_pass.set_bind_group(0, &page.bind_group, &[]);
             render_pass.draw_indexed(
                 0..Self::INDICES.len() as u32,
                 0,
-                0..tile_layer.instance_count as u32,
+                range.start + translucent_start..range.end + translucent_start,
             );
         }
 
-        // Render Sprite Sheets
-        render_pass.set_pipeline(&self.pixel_art.sprite_sheet_render_pipeline);
+        drop(render_pass);
+    }
 
-        for (sprite_sheet, instance_buffer) in sprite_sheet_iter.iter() {
-            render_pass.set_bind_group(3, &sprite_sheet.data_bind_group, &[]);
-            render_pass.set_bind_group(0, &sprite_sheet.texture.bind_group, &[]);
-            render_pass
-                .set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.set_vertex_buffer(0, sprite_sheet.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-            render_pass.draw_indexed(
-                0..Self::INDICES.len() as u32,
-                0,
-                0..sprite_sheet.instances.borrow().len() as u32,
-            );
-            sprite_sheet.instances.borrow_mut().clear();
+    fn draw_downscale(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let clear_color = match &self.downscaling.letterbox {
+            Letterbox::Color(color) => *color,
+            Letterbox::Image(_) => wgpu::Color::BLACK,
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.downscaling.render_pipeline);
+        render_pass.set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+        if let Letterbox::Image(border) = &self.downscaling.letterbox {
+            render_pass.set_bind_group(0, &border.bind_group, &[]);
+            render_pass.set_bind_group(1, &self.downscaling.border_data_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.downscaling.border_vertex_buffer.slice(..));
+            render_pass.draw_indexed(0..Self::INDICES.len() as u32, 0, 0..1);
         }
 
-        drop(render_pass);
+        render_pass.set_bind_group(0, &self.pixel_art.texture.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.downscaling.data_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.downscaling.vertex_buffer.slice(..));
+        render_pass.draw_indexed(0..Self::INDICES.len() as u32, 0, 0..1);
 
-        // Render To Screen
-        let screen = self.surface.get_current_texture().unwrap();
-        let view = screen
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        drop(render_pass);
+    }
 
+    /// Draws the pixel art texture into the capture target, with no letterbox or quantization.
+    fn draw_capture(&self, encoder: &mut wgpu::CommandEncoder) {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
+                view: &self.capture.view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.0,
-                        g: 0.0,
-                        b: 0.0,
-                        a: 1.0,
-                    }),
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                     store: true,
                 },
             })],
@@ -890,47 +1233,97 @@ impl Renderer {
         });
 
         render_pass.set_pipeline(&self.downscaling.render_pipeline);
-        render_pass.set_bind_group(0, &self.pixel_art.texture.bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.downscaling.vertex_buffer.slice(..));
         render_pass.set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.set_bind_group(0, &self.pixel_art.texture.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.downscaling.capture_data_bind_group, &[]);
+        // the border covers the whole target
+        render_pass.set_vertex_buffer(0, self.downscaling.border_vertex_buffer.slice(..));
         render_pass.draw_indexed(0..Self::INDICES.len() as u32, 0, 0..1);
 
         drop(render_pass);
+    }
+}
 
-        self.queue.submit(iter::once(encoder.finish()));
+impl AssetLoader for Renderer {
+    /// Packs `image` into the atlas.
+    fn texture(&self, image: RgbaImage) -> AtlasTexture {
+        self.atlas.borrow_mut().add(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            image,
+        )
+    }
 
-        screen.present();
+    fn sprite_ids(&self) -> &Rc<RefCell<HashSet<usize>>> {
+        &self.sprite_ids
     }
 }
 
 impl<'a> DrawQueue<'a> {
     pub fn new() -> Self {
-        Self(vec![])
+        Self {
+            jobs: vec![],
+            layer: Layer::default(),
+            y_sorted: HashSet::new(),
+        }
     }
 
+    /// Jobs keep the layer they were queued on, and layers y-sorted in either queue stay that way.
     pub fn append(&mut self, mut dq: DrawQueue<'a>) {
-        self.0.append(&mut dq.0);
+        self.jobs.append(&mut dq.jobs);
+        self.y_sorted.extend(dq.y_sorted);
+    }
+
+    /// Puts every job queued after this on `layer`.
+    pub fn set_layer(&mut self, layer: Layer) {
+        self.layer = layer;
+    }
+
+    /// Draws the jobs on `layer` from top to bottom by their position, no matter what order they
+    /// were queued in. Sprites drawn from their feet then overlap the way you'd expect.
+    pub fn y_sort(&mut self, layer: Layer) {
+        self.y_sorted.insert(layer);
+    }
+
+    /// Every job in the order it should be drawn, back to front.
+    pub fn into_sorted(self) -> Vec<DrawJob<'a>> {
+        let mut jobs = self.jobs;
+        // stable, so jobs that aren't y-sorted stay in the order they were queued
+        jobs.sort_by(|(a_layer, a), (b_layer, b)| {
+            a_layer.cmp(b_layer).then_with(|| {
+                if self.y_sorted.contains(a_layer) {
+                    a.y().total_cmp(&b.y())
+                } else {
+                    Ordering::Equal
+                }
+            })
+        });
+        jobs.into_iter().map(|(_, job)| job).collect()
+    }
+
+    fn push(&mut self, job: DrawJob<'a>) {
+        self.jobs.push((self.layer, job));
     }
 
     pub fn sprite(&mut self, sprite: &'a Sprite, params: DrawParams) {
-        self.0.push(DrawJob::Sprite(sprite, params));
+        self.push(DrawJob::Sprite(sprite, params));
     }
 
     pub fn sheet(&mut self, sprite_sheet: &'a SpriteSheet, t: u32, params: DrawParams) {
-        self.0.push(DrawJob::SpriteSheet(sprite_sheet, t, params));
+        self.push(DrawJob::SpriteSheet(sprite_sheet, t, params));
     }
 
     /// Tile layers may only be drawn once. Future draw calls on the same tile layer will be ignored.
     pub fn tile_layer(&mut self, tile_map: &'a TileMap, layer: &str) {
-        self.0
-            .push(DrawJob::TileLayer(tile_map.tile_layers.get(layer).unwrap()));
+        self.push(DrawJob::TileLayer(tile_map.tile_layers.get(layer).unwrap()));
     }
 
     /// Tile layers may only be drawn once. Future draw calls on the same tile layer will be ignored.
     pub fn tile_image(&mut self, tile_map: &'a TileMap, layer: &str) {
         let image_layer = tile_map.image_layers.get(layer).unwrap();
         let mut draw_spr = |offset| {
-            self.0.push(DrawJob::Sprite(
+            self.push(DrawJob::Sprite(
                 &image_layer.sprite,
                 DrawParams::from_pos(offset + image_layer.position),
             ))
@@ -0,0 +1,244 @@
+//! Packs sprite images into a few big textures, so sprites that share one can be drawn together.
+
+use std::{cell::RefCell, num::NonZeroU32, rc::Rc};
+
+use glam::*;
+use image::RgbaImage;
+
+use super::quad::QuadInstance;
+
+/// Pixels around every packed image. The image's edges are copied into them, so sampling right
+/// at its border can't pick up whatever was packed next to it.
+pub const PADDING: u32 = 2;
+/// Width and height of a page, unless the device doesn't allow textures that big.
+pub const PAGE_SIZE: u32 = 2048;
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Where the next image on this shelf goes.
+    x: u32,
+}
+
+/// Shelf packer. Images go left to right along shelves, which are as tall as the first image put
+/// on them and get stacked top to bottom.
+pub struct Packer {
+    size: UVec2,
+    shelves: Vec<Shelf>,
+}
+
+impl Packer {
+    pub fn new(size: UVec2) -> Self {
+        Self {
+            size,
+            shelves: vec![],
+        }
+    }
+
+    /// Finds room for a rectangle of `size`, returning its top left corner.
+    pub fn pack(&mut self, size: UVec2) -> Option<UVec2> {
+        // the lowest shelf it fits on wastes the least space
+        let width = self.size.x;
+        if let Some(shelf) = self
+            .shelves
+            .iter_mut()
+            .filter(|shelf| shelf.height >= size.y && width - shelf.x >= size.x)
+            .min_by_key(|shelf| shelf.height)
+        {
+            let corner = UVec2::new(shelf.x, shelf.y);
+            shelf.x += size.x;
+            return Some(corner);
+        }
+
+        let y = self
+            .shelves
+            .last()
+            .map_or(0, |shelf| shelf.y + shelf.height);
+        if size.x > self.size.x || y + size.y > self.size.y {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height: size.y,
+            x: size.x,
+        });
+        Some(UVec2::new(0, y))
+    }
+}
+
+#[derive(Debug)]
+pub struct AtlasPage {
+    pub id: usize,
+    pub texture: wgpu::Texture,
+    pub bind_group: wgpu::BindGroup,
+    pub size: UVec2,
+    /// Opaque sprite instances drawn from this page in the current frame.
+    pub instances: RefCell<Vec<QuadInstance>>,
+}
+
+/// An image packed into an atlas page.
+#[derive(Debug, Clone)]
+pub struct AtlasTexture {
+    /// `None` for textures that only live on the CPU, which only the software renderer can draw.
+    pub page: Option<Rc<AtlasPage>>,
+    /// Left, top, right and bottom edges of the image in the page, from 0 to 1.
+    pub uv: Vec4,
+    pub size: UVec2,
+    /// CPU copy of the image, so it can be rasterized in software.
+    pub image: RgbaImage,
+    /// Whether any texel is partly transparent, which means it has to be drawn in the
+    /// translucent pass.
+    pub translucent: bool,
+}
+
+impl AtlasTexture {
+    /// A texture that isn't packed into any page, for drawing with the software renderer without
+    /// a device.
+    pub fn cpu_only(image: RgbaImage) -> Self {
+        Self {
+            page: None,
+            uv: Vec4::new(0.0, 0.0, 1.0, 1.0),
+            size: UVec2::from(image.dimensions()),
+            translucent: is_translucent(&image),
+            image,
+        }
+    }
+}
+
+/// Whether any texel is partly transparent.
+fn is_translucent(image: &RgbaImage) -> bool {
+    image.pixels().any(|p| p[3] > 0 && p[3] < 255)
+}
+
+pub struct Atlas {
+    pages: Vec<(Rc<AtlasPage>, Packer)>,
+    page_size: u32,
+}
+
+impl Atlas {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            pages: vec![],
+            page_size: PAGE_SIZE.min(device.limits().max_texture_dimension_2d),
+        }
+    }
+
+    /// Packs `image` into the first page with room for it, starting a new page if none have any.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        image: RgbaImage,
+    ) -> AtlasTexture {
+        let size = UVec2::from(image.dimensions());
+        let padded_size = size + 2 * PADDING;
+
+        let found = self
+            .pages
+            .iter_mut()
+            .find_map(|(page, packer)| Some((page.clone(), packer.pack(padded_size)?)));
+        let (page, corner) = match found {
+            Some(found) => found,
+            None => {
+                // images too big for a normal page get one to themselves
+                let page_size = padded_size.max(UVec2::splat(self.page_size));
+                let mut packer = Packer::new(page_size);
+                let corner = packer.pack(padded_size).unwrap();
+                let page = Rc::new(Self::create_page(
+                    device,
+                    layout,
+                    self.pages.len(),
+                    page_size,
+                ));
+                self.pages.push((page.clone(), packer));
+                (page, corner)
+            }
+        };
+
+        let padded = RgbaImage::from_fn(padded_size.x, padded_size.y, |x, y| {
+            let x = (x as i64 - PADDING as i64).clamp(0, size.x as i64 - 1);
+            let y = (y as i64 - PADDING as i64).clamp(0, size.y as i64 - 1);
+            *image.get_pixel(x as u32, y as u32)
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &page.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: corner.x,
+                    y: corner.y,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &padded,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * padded_size.x),
+                rows_per_image: NonZeroU32::new(padded_size.y),
+            },
+            wgpu::Extent3d {
+                width: padded_size.x,
+                height: padded_size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let top_left = (corner + PADDING).as_vec2() / page.size.as_vec2();
+        let bottom_right = (corner + PADDING + size).as_vec2() / page.size.as_vec2();
+        AtlasTexture {
+            uv: Vec4::new(top_left.x, top_left.y, bottom_right.x, bottom_right.y),
+            page: Some(page),
+            size,
+            translucent: is_translucent(&image),
+            image,
+        }
+    }
+
+    fn create_page(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        id: usize,
+        size: UVec2,
+    ) -> AtlasPage {
+        // wgpu zeroes new textures, so unused space is transparent
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        AtlasPage {
+            id,
+            texture,
+            bind_group,
+            size,
+            instances: RefCell::new(vec![]),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+// Per draw colour effects, shared by every shader that draws sprites.
+
+// `tint` multiplies the colour and its alpha is the opacity, `flash` mixes towards its rgb by its
+// alpha. The result is premultiplied, since that's how the pipelines blend.
+fn apply_effects(color: vec4<f32>, tint: vec4<f32>, flash: vec4<f32>) -> vec4<f32> {
+    let rgb = mix(color.rgb * tint.rgb, flash.rgb, flash.a);
+    let a = color.a * tint.a;
+    return vec4<f32>(rgb * a, a);
+}
+
@@ -0,0 +1,48 @@
+//! One vertex buffer holding every quad instance drawn in a frame. It's kept between frames and
+//! rewritten with `queue.write_buffer`, so it only gets reallocated when a frame draws more
+//! instances than it has ever drawn before.
+
+use super::quad::QuadInstance;
+
+/// Instances the buffer starts with room for.
+const INITIAL_CAPACITY: u64 = 1024;
+
+pub struct InstanceBuffer {
+    pub buffer: wgpu::Buffer,
+    /// How many instances fit in `buffer`.
+    pub capacity: u64,
+    /// This frame's instances, batches are ranges into it. Kept around so its allocation is
+    /// reused too.
+    pub instances: Vec<QuadInstance>,
+}
+
+impl InstanceBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            buffer: Self::create_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            instances: vec![],
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("quad instances"),
+            size: capacity * std::mem::size_of::<QuadInstance>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Uploads `instances`, growing the buffer to the next power of two if they don't fit.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let len = self.instances.len() as u64;
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        if len > 0 {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.instances));
+        }
+    }
+}
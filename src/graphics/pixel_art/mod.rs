@@ -1,22 +1,31 @@
+pub mod atlas;
+pub mod instance_buffer;
+pub mod palette;
+pub mod quad;
+pub mod rotsprite;
 pub mod sprite;
 pub mod sprite_sheet;
 pub mod texture;
 pub mod tilemap;
 
 use glam::*;
-use sprite::*;
+use palette::*;
+use quad::QuadInstance;
 use texture::*;
 use wgpu::util::DeviceExt;
 
 use crate::math::extend3d_to_uvec2;
 
-pub const PIXEL_PREC: u32 = 5;
-
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Camera {
     offset: [f32; 2],
 }
+impl Camera {
+    pub fn offset(&self) -> Vec2 {
+        Vec2::from(self.offset)
+    }
+}
 impl From<Vec2> for Camera {
     fn from(offset: Vec2) -> Self {
         Self {
@@ -49,36 +58,37 @@ impl WorldToPixel {
 }
 
 pub struct PixelArt {
+    pub pixel_prec: u32,
     pub w2p: GPUWorldToPixel,
     pub texture: GPUTexture,
     pub depth_texture: TextureRaw,
 
-    pub sprite_shader: wgpu::ShaderModule,
-    pub sprite_sheet_shader: wgpu::ShaderModule,
-    pub tile_layer_shader: wgpu::ShaderModule,
-    pub sprite_pipeline_layout: wgpu::PipelineLayout,
-    pub sprite_render_pipeline: wgpu::RenderPipeline,
-    pub sprite_sheet_pipeline_layout: wgpu::PipelineLayout,
-    pub sprite_sheet_render_pipeline: wgpu::RenderPipeline,
-    pub tile_layer_pipeline_layout: wgpu::PipelineLayout,
-    pub tile_layer_render_pipeline: wgpu::RenderPipeline,
+    pub quad_shader: wgpu::ShaderModule,
+    pub quad_pipeline_layout: wgpu::PipelineLayout,
+    pub quad_render_pipeline: wgpu::RenderPipeline,
+    // same as the one above, but it doesn't write depth, so translucent things drawn back to
+    // front don't hide each other
+    pub quad_translucent_pipeline: wgpu::RenderPipeline,
 
     pub camera: Camera,
     pub camera_buffer: wgpu::Buffer,
+    pub camera_bind_group_layout: wgpu::BindGroupLayout,
+    /// Also holds the palette lookup texture, since the quad pipeline binds it anyway.
     pub camera_bind_group: wgpu::BindGroup,
 
-    pub tile_depth_bind_group_layout: wgpu::BindGroupLayout,
-
-    pub sprite_sheet_data_bind_group_layout: wgpu::BindGroupLayout,
+    pub palettes: Palettes,
 }
 
 impl PixelArt {
+    pub const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+
     pub fn new(
         device: &wgpu::Device,
         camera_width: u32,
         camera_height: u32,
         config: &wgpu::SurfaceConfiguration,
         texture_bind_group_layout: &wgpu::BindGroupLayout,
+        pixel_prec: u32,
     ) -> Self {
         let camera = Camera::from(00.0 * Vec2::ONE);
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -89,25 +99,36 @@ impl PixelArt {
         let camera_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                ],
             });
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-        });
+        let palettes = Palettes::new(device);
+        let camera_bind_group = Self::create_camera_bind_group(
+            device,
+            &camera_bind_group_layout,
+            &camera_buffer,
+            &palettes,
+        );
 
         let w2p = {
             let raw = WorldToPixel::new(camera_width, camera_height);
@@ -146,295 +167,241 @@ impl PixelArt {
             }
         };
 
-        let texture = {
-            let size = wgpu::Extent3d {
-                width: PIXEL_PREC * camera_width,
-                height: PIXEL_PREC * camera_height,
-                depth_or_array_layers: 1,
-            };
-            let render_texture = device.create_texture(&wgpu::TextureDescriptor {
-                label: None,
-                size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                    | wgpu::TextureUsages::TEXTURE_BINDING
-                    | wgpu::TextureUsages::COPY_SRC,
-            });
-            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Linear,
-                mipmap_filter: wgpu::FilterMode::Linear,
-                ..Default::default()
-            });
-            let view = render_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None,
-                layout: texture_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&sampler),
-                    },
-                ],
-            });
-
-            GPUTexture {
-                texture: render_texture,
-                sampler,
-                view,
-                bind_group,
-                size: extend3d_to_uvec2(&size),
-            }
-        };
+        let texture = Self::create_texture(
+            device,
+            camera_width,
+            camera_height,
+            pixel_prec,
+            texture_bind_group_layout,
+        );
 
-        let depth_texture = TextureRaw::create_depth_texture(&device, camera_width, camera_height);
+        let depth_texture =
+            TextureRaw::create_depth_texture(device, camera_width, camera_height, pixel_prec);
 
-        let sprite_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        let quad_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(include_str!("sprite.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("palette.wgsl"),
+                    include_str!("effects.wgsl"),
+                    include_str!("quad.wgsl")
+                )
+                .into(),
+            ),
         });
 
-        let sprite_sheet_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        let quad_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(include_str!("sprite_sheet.wgsl").into()),
+            bind_group_layouts: &[
+                texture_bind_group_layout,
+                &w2p.bind_group_layout,
+                &camera_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
         });
 
-        let tile_layer_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(include_str!("tile_layer.wgsl").into()),
-        });
+        let quad_render_pipeline = Self::create_pipeline(
+            device,
+            &quad_pipeline_layout,
+            &quad_shader,
+            config.format,
+            true,
+        );
+        let quad_translucent_pipeline = Self::create_pipeline(
+            device,
+            &quad_pipeline_layout,
+            &quad_shader,
+            config.format,
+            false,
+        );
 
-        let sprite_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[
-                    &texture_bind_group_layout,
-                    &w2p.bind_group_layout,
-                    &camera_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
+        Self {
+            pixel_prec,
+            texture,
+            depth_texture,
+            w2p,
+            quad_shader,
+            quad_pipeline_layout,
+            quad_render_pipeline,
+            quad_translucent_pipeline,
+            camera,
+            camera_buffer,
+            camera_bind_group_layout,
+            camera_bind_group,
+            palettes,
+        }
+    }
 
-        let sprite_render_pipeline =
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&sprite_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &sprite_shader,
-                    entry_point: "vs_main",
-                    buffers: &[sprite::Vertex::desc(), SpriteInstance::desc()],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &sprite_shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent::OVER,
-                            alpha: wgpu::BlendComponent::OVER,
-                        }),
-                        write_mask: wgpu::ColorWrites::COLOR,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    unclipped_depth: false,
-                    conservative: false,
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: TextureRaw::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }),
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                multiview: None,
-            });
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        depth_write_enabled: bool,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[quad::Vertex::desc(), QuadInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent::OVER,
+                        alpha: wgpu::BlendComponent::OVER,
+                    }),
+                    write_mask: wgpu::ColorWrites::COLOR,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // negative scales flip the winding order
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: TextureRaw::DEPTH_FORMAT,
+                depth_write_enabled,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
 
-        let sprite_sheet_data_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
+    fn create_camera_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+        palettes: &Palettes,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
-
-        let sprite_sheet_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[
-                    &texture_bind_group_layout,
-                    &w2p.bind_group_layout,
-                    &camera_bind_group_layout,
-                    &sprite_sheet_data_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
-        let sprite_sheet_render_pipeline =
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&sprite_sheet_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &sprite_sheet_shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        sprite_sheet::Vertex::desc(),
-                        sprite_sheet::SpriteSheetInstance::desc(),
-                    ],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &sprite_sheet_shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent::OVER,
-                            alpha: wgpu::BlendComponent::OVER,
-                        }),
-                        write_mask: wgpu::ColorWrites::COLOR,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    unclipped_depth: false,
-                    conservative: false,
+                    resource: camera_buffer.as_entire_binding(),
                 },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: TextureRaw::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }),
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&palettes.view),
                 },
-                multiview: None,
-            });
+            ],
+        })
+    }
 
-        let tile_depth_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
+    pub fn add_palette(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        palette: Palette,
+    ) -> PaletteHandle {
+        let handle = self.palettes.add(device, queue, palette);
+        self.camera_bind_group = Self::create_camera_bind_group(
+            device,
+            &self.camera_bind_group_layout,
+            &self.camera_buffer,
+            &self.palettes,
+        );
+        handle
+    }
 
-        let tile_layer_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[
-                    &texture_bind_group_layout,
-                    &w2p.bind_group_layout,
-                    &camera_bind_group_layout,
-                    &tile_depth_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
+    /// The render target, `pixel_prec` times bigger than the camera in each direction.
+    pub fn create_texture(
+        device: &wgpu::Device,
+        camera_width: u32,
+        camera_height: u32,
+        pixel_prec: u32,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> GPUTexture {
+        let size = wgpu::Extent3d {
+            width: pixel_prec * camera_width,
+            height: pixel_prec * camera_height,
+            depth_or_array_layers: 1,
+        };
+        let render_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let view = render_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        let tile_layer_render_pipeline =
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&tile_layer_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &tile_layer_shader,
-                    entry_point: "vs_main",
-                    buffers: &[tilemap::Vertex::desc(), tilemap::TileInstance::desc()],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &tile_layer_shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent::OVER,
-                            alpha: wgpu::BlendComponent::OVER,
-                        }),
-                        write_mask: wgpu::ColorWrites::COLOR,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    unclipped_depth: false,
-                    conservative: false,
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
                 },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: TextureRaw::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }),
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
                 },
-                multiview: None,
-            });
+            ],
+        });
 
-        Self {
-            texture,
-            depth_texture,
-            w2p,
-            sprite_pipeline_layout,
-            sprite_sheet_pipeline_layout,
-            camera,
-            camera_buffer,
-            camera_bind_group,
-            sprite_shader,
-            sprite_sheet_shader,
-            sprite_render_pipeline,
-            sprite_sheet_render_pipeline,
-            tile_layer_pipeline_layout,
-            tile_layer_render_pipeline,
-            tile_layer_shader,
-            tile_depth_bind_group_layout,
-            sprite_sheet_data_bind_group_layout,
+        GPUTexture {
+            texture: render_texture,
+            sampler,
+            view,
+            bind_group,
+            size: extend3d_to_uvec2(&size),
         }
     }
+
+    /// Supersampling factor that gives at least one texel per screen pixel, within the device's
+    /// texture size limits.
+    pub fn pixel_prec_for(
+        device: &wgpu::Device,
+        screen_width: u32,
+        screen_height: u32,
+        camera_width: u32,
+        camera_height: u32,
+    ) -> u32 {
+        let max_prec = Self::max_pixel_prec(device, camera_width, camera_height);
+        let scale = f32::max(
+            screen_width as f32 / camera_width as f32,
+            screen_height as f32 / camera_height as f32,
+        );
+        (scale.ceil() as u32).clamp(1, max_prec)
+    }
+
+    /// Biggest supersampling factor the device can make a texture for.
+    pub fn max_pixel_prec(device: &wgpu::Device, camera_width: u32, camera_height: u32) -> u32 {
+        let max = device.limits().max_texture_dimension_2d;
+        (max / camera_width).min(max / camera_height).max(1)
+    }
 }
@@ -0,0 +1,119 @@
+use wgpu::util::DeviceExt;
+
+/// Most colours a single palette can swap.
+pub const MAX_PALETTE_COLORS: usize = 64;
+
+/// Refers to a palette created by the renderer. Pass it in `DrawParams` to draw a sprite with
+/// its colours swapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PaletteHandle(pub u32);
+
+/// Maps each colour in `from` to the colour at the same index in `to`. Colours are matched on
+/// rgb only, and the sprite's alpha is multiplied with the replacement's.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    pub from: Vec<[u8; 4]>,
+    pub to: Vec<[u8; 4]>,
+}
+
+impl Palette {
+    pub fn apply(&self, color: [u8; 4]) -> [u8; 4] {
+        if color[3] == 0 {
+            return color;
+        }
+        match self.from.iter().position(|c| c[..3] == color[..3]) {
+            Some(i) => {
+                let [r, g, b, a] = self.to[i];
+                [r, g, b, ((a as u32 * color[3] as u32 + 127) / 255) as u8]
+            }
+            None => color,
+        }
+    }
+
+    /// Whether any colour gets swapped for one that's partly transparent.
+    pub fn is_translucent(&self) -> bool {
+        self.to.iter().any(|c| c[3] < 255)
+    }
+}
+
+/// Every palette packed into one lookup texture, two rows per palette. The first row holds the
+/// colours to look for and the second one what they become. Unused slots are fully transparent,
+/// which ends the search in the shader.
+pub struct Palettes {
+    pub palettes: Vec<Palette>,
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl Palettes {
+    pub fn new(device: &wgpu::Device) -> Self {
+        // wgpu zeroes new textures, so this is a single palette that doesn't match anything
+        let texture = device.create_texture(&Self::descriptor(1));
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            palettes: vec![],
+            texture,
+            view,
+        }
+    }
+
+    fn descriptor(count: u32) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: MAX_PALETTE_COLORS as u32,
+                height: count * 2,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // same format as loaded textures, so the shader compares like with like
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+
+    pub fn get(&self, handle: PaletteHandle) -> Option<&Palette> {
+        self.palettes.get(handle.0 as usize)
+    }
+
+    /// Adds a palette and rebuilds the lookup texture, so the bind group using it has to be
+    /// recreated afterwards. `Renderer::create_palette` checks that the palette fits first.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        palette: Palette,
+    ) -> PaletteHandle {
+        self.palettes.push(palette);
+
+        let mut data = vec![0; self.palettes.len() * 2 * MAX_PALETTE_COLORS * 4];
+        let row_len = MAX_PALETTE_COLORS * 4;
+        for (i, palette) in self.palettes.iter().enumerate() {
+            for (row, colors) in [&palette.from, &palette.to].into_iter().enumerate() {
+                let start = (i * 2 + row) * row_len;
+                for (j, color) in colors.iter().enumerate() {
+                    // a transparent key marks the end of the palette, so keys are always opaque
+                    let color = if row == 0 {
+                        [color[0], color[1], color[2], 255]
+                    } else {
+                        *color
+                    };
+                    data[start + j * 4..start + j * 4 + 4].copy_from_slice(&color);
+                }
+            }
+        }
+
+        self.texture = device.create_texture_with_data(
+            queue,
+            &Self::descriptor(self.palettes.len() as u32),
+            &data,
+        );
+        self.view = self
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        PaletteHandle(self.palettes.len() as u32 - 1)
+    }
+}
@@ -0,0 +1,24 @@
+// Palette swapping, shared by every shader that draws sprites. See palette.rs for the layout.
+@group(2) @binding(1)
+var t_palettes: texture_2d<f32>;
+
+fn swap_palette(color: vec4<f32>, palette: i32) -> vec4<f32> {
+    // sprites are blended as if premultiplied, so transparent texels have to stay black
+    if (palette < 0 || color.a == 0.0) {
+        return color;
+    }
+    let width = textureDimensions(t_palettes).x;
+    for (var i = 0; i < width; i++) {
+        let key = textureLoad(t_palettes, vec2<i32>(i, palette * 2), 0);
+        if (key.a == 0.0) {
+            break;
+        }
+        // both textures are srgb, so the same colour decodes to the same value
+        if (all(abs(key.rgb - color.rgb) < vec3<f32>(0.0001))) {
+            let to = textureLoad(t_palettes, vec2<i32>(i, palette * 2 + 1), 0);
+            return vec4<f32>(to.rgb, to.a * color.a);
+        }
+    }
+    return color;
+}
+
@@ -0,0 +1,107 @@
+//! Everything pixel art is drawn as instances of one unit quad, so sprites, sprite sheet frames
+//! and tiles from the same atlas page all end up in the same draw call.
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 2],
+    pub uv_mask: i32,
+}
+
+impl Vertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Sint32];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+
+    pub fn from_size(width: f32, height: f32) -> [Self; 4] {
+        [
+            Self {
+                position: [0.0, 0.0],
+                uv_mask: crate::graphics::TOP_LEFT,
+            },
+            Self {
+                position: [width, 0.0],
+                uv_mask: crate::graphics::TOP_RIGHT,
+            },
+            Self {
+                position: [0.0, height],
+                uv_mask: crate::graphics::BOTTOM_LEFT,
+            },
+            Self {
+                position: [width, height],
+                uv_mask: crate::graphics::BOTTOM_RIGHT,
+            },
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct QuadInstance {
+    /// Top left corner, and depth in z.
+    pub offset: [f32; 4],
+    pub flip_mask: i32,
+    pub camera_locked: i32,
+    /// Index of the palette to draw with, or -1.
+    pub palette: i32,
+    /// Multiplied with the colour, with the opacity in alpha.
+    pub tint: [f32; 4],
+    /// Colour to mix towards, with how far to go in alpha.
+    pub flash: [f32; 4],
+    /// Point that rotation and scale happen around, relative to `offset`.
+    pub pivot: [f32; 2],
+    pub scale: [f32; 2],
+    /// Cosine and sine of the rotation.
+    pub rotation: [f32; 2],
+    /// Left, top, right and bottom edges of the quad's texture in its atlas page.
+    pub uv: [f32; 4],
+    pub size: [f32; 2],
+}
+
+impl QuadInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 11] = wgpu::vertex_attr_array![
+        5 => Float32x4,
+        6 => Sint32,
+        7 => Sint32,
+        8 => Sint32,
+        9 => Float32x4,
+        10 => Float32x4,
+        11 => Float32x2,
+        12 => Float32x2,
+        13 => Float32x2,
+        14 => Float32x4,
+        15 => Float32x2,
+    ];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+
+    /// A plain quad, drawn as is. Used for tiles.
+    pub fn new(offset: [f32; 2], size: [f32; 2], uv: [f32; 4], flip_mask: i32) -> Self {
+        Self {
+            offset: [offset[0], offset[1], 0.0, 0.0],
+            flip_mask,
+            camera_locked: 0,
+            palette: -1,
+            tint: [1.0; 4],
+            flash: [0.0; 4],
+            pivot: [0.0; 2],
+            scale: [1.0; 2],
+            rotation: [1.0, 0.0],
+            uv,
+            size,
+        }
+    }
+}
@@ -7,6 +7,9 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) palette: i32,
+    @location(2) @interpolate(flat) tint: vec4<f32>,
+    @location(3) @interpolate(flat) flash: vec4<f32>,
 }
 
 struct WorldToPixel {
@@ -21,6 +24,16 @@ struct Instance {
     @location(5) offset: vec3<f32>,
     @location(6) flip_mask: i32,
     @location(7) camera_locked: i32,
+    @location(8) palette: i32,
+    @location(9) tint: vec4<f32>,
+    @location(10) flash: vec4<f32>,
+    @location(11) pivot: vec2<f32>,
+    @location(12) scale: vec2<f32>,
+    // cosine and sine
+    @location(13) rotation: vec2<f32>,
+    // left, top, right and bottom in the atlas page
+    @location(14) uv: vec4<f32>,
+    @location(15) size: vec2<f32>,
 }
 
 struct Camera {
@@ -35,13 +48,16 @@ fn vs_main(
     instance: Instance,
 ) -> VertexOutput {
     var out: VertexOutput;
+    out.palette = instance.palette;
+    out.tint = instance.tint;
+    out.flash = instance.flash;
 
     let flip_h = instance.flip_mask % 2 == 1;
     let flip_v = instance.flip_mask >= 2;
-    let uv_left = select(0.0, 1.0, flip_h);
-    let uv_right = select(1.0, 0.0, flip_h);
-    let uv_top = select(0.0, 1.0, flip_v);
-    let uv_bottom = select(1.0, 0.0, flip_v);
+    let uv_left = select(instance.uv.x, instance.uv.z, flip_h);
+    let uv_right = select(instance.uv.z, instance.uv.x, flip_h);
+    let uv_top = select(instance.uv.y, instance.uv.w, flip_v);
+    let uv_bottom = select(instance.uv.w, instance.uv.y, flip_v);
 
     let uv_mask = in.uv_mask;
     switch uv_mask {
@@ -60,7 +76,12 @@ fn vs_main(
         default: {}
     }
     //out.uv = vec2<f32>(0.5);
-    var world_pos = in.position.xy + w2p.offset + instance.offset.xy;
+    // the vertices are a unit quad shared by everything
+    let local = (in.position.xy * instance.size - instance.pivot) * instance.scale;
+    let c = instance.rotation.x;
+    let s = instance.rotation.y;
+    let rotated = vec2<f32>(c * local.x - s * local.y, s * local.x + c * local.y);
+    var world_pos = rotated + instance.pivot + w2p.offset + instance.offset.xy;
     // if we're not drawing camera locked ui
     if (instance.camera_locked == 0) {
         world_pos -= camera.offset;
@@ -82,8 +103,9 @@ var t_sampler: sampler;
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let color = textureSample(t_texture, t_sampler, in.uv);
-    if (color.a == 0.0) {
+    let out = apply_effects(swap_palette(color, in.palette), in.tint, in.flash);
+    if (out.a == 0.0) {
         discard;
     }
-    return color;
+    return out;
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use std::{cell::RefCell, collections::HashSet, rc::Rc};
 
 use glam::*;
 
-use super::texture::GPUTexture;
+use super::atlas::AtlasTexture;
 
 #[derive(Debug)]
 pub enum Origin {
@@ -35,38 +35,14 @@ impl Origin {
     }
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct SpriteInstance {
-    pub offset: [f32; 4],
-    pub flip_mask: i32,
-    pub ui: i32,
-}
-
-impl SpriteInstance {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
-        5 => Float32x4,
-        6 => Sint32,
-        7 => Sint32,
-    ];
-
-    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<SpriteInstance>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &Self::ATTRIBUTES,
-        }
-    }
-}
-
 #[derive(Debug)]
 pub struct Sprite {
-    pub vertex_buffer: wgpu::Buffer,
     pub id: usize,
     pub ids: Rc<RefCell<HashSet<usize>>>,
-    pub texture: GPUTexture,
+    pub texture: AtlasTexture,
     pub origin: Origin,
-    pub instances: RefCell<Vec<SpriteInstance>>,
+    /// Evenly spaced rotations, starting at none. See `AssetLoader::cache_rotations`.
+    pub rotations: Vec<Sprite>,
 }
 
 impl Drop for Sprite {
@@ -80,44 +56,3 @@ impl Sprite {
         self.origin.as_vec2(self.texture.size.as_vec2())
     }
 }
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Vertex {
-    pub position: [f32; 2],
-    pub uv_mask: i32,
-}
-
-impl Vertex {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x2, 1 => Sint32];
-
-    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBUTES,
-        }
-    }
-
-    pub fn from_size(width: f32, height: f32) -> [Self; 4] {
-        [
-            Self {
-                position: [0.0, 0.0],
-                uv_mask: crate::graphics::TOP_LEFT,
-            },
-            Self {
-                position: [width, 0.0],
-                uv_mask: crate::graphics::TOP_RIGHT,
-            },
-            Self {
-                position: [0.0, height],
-                uv_mask: crate::graphics::BOTTOM_LEFT,
-            },
-            Self {
-                position: [width, height],
-                uv_mask: crate::graphics::BOTTOM_RIGHT,
-            },
-        ]
-    }
-}
@@ -2,9 +2,9 @@ use std::{cell::RefCell, collections::HashSet, rc::Rc};
 
 use glam::*;
 
-use super::{sprite::Origin, texture::GPUTexture};
+use super::{atlas::AtlasTexture, sprite::Origin};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Orientation {
     Vertical,
     Horizontal,
@@ -30,15 +30,13 @@ pub enum FrameRate {
 pub struct SpriteSheet {
     pub id: usize,
     pub ids: Rc<RefCell<HashSet<usize>>>,
-    pub instances: RefCell<Vec<SpriteSheetInstance>>,
-    pub vertex_buffer: wgpu::Buffer,
-    pub texture: GPUTexture,
+    pub texture: AtlasTexture,
     pub origin: Origin,
     pub count: u8,
     pub frame_rate: FrameRate,
     pub orientation: Orientation,
-    pub data_buffer: wgpu::Buffer,
-    pub data_bind_group: wgpu::BindGroup,
+    /// Evenly spaced rotations, starting at none. See `AssetLoader::cache_sheet_rotations`.
+    pub rotations: Vec<SpriteSheet>,
 }
 
 impl SpriteSheet {
@@ -84,66 +82,21 @@ impl SpriteSheet {
     pub fn origin(&self) -> Vec2 {
         self.origin.as_vec2(self.size().as_vec2())
     }
-}
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Vertex {
-    pub position: [f32; 2],
-    pub uv_mask: i32,
-}
-
-impl Vertex {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x2, 1 => Sint32];
-
-    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBUTES,
-        }
-    }
-}
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct SpriteSheetInstance {
-    pub offset: [f32; 4],
-    pub flip_mask: i32,
-    pub t: i32,
-}
 
-impl SpriteSheetInstance {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
-        wgpu::vertex_attr_array![5 => Float32x4, 6 => Sint32, 7 => Sint32];
-
-    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &Self::ATTRIBUTES,
-        }
-    }
-}
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct SpriteSheetData {
-    pub count: i32,
-    pub horizontal: i32,
-}
-
-impl SpriteSheetData {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![8 => Sint32, 9 => Sint32];
-
-    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBUTES,
-        }
+    /// Left, top, right and bottom edges of frame `t` in the atlas page.
+    pub fn frame_uv(&self, t: u32) -> Vec4 {
+        let uv = self.texture.uv;
+        let frame_size = (uv.zw() - uv.xy())
+            / match self.orientation {
+                Orientation::Vertical => Vec2::new(1.0, self.count as f32),
+                Orientation::Horizontal => Vec2::new(self.count as f32, 1.0),
+            };
+        let frame_offset = match self.orientation {
+            Orientation::Vertical => Vec2::new(0.0, frame_size.y),
+            Orientation::Horizontal => Vec2::new(frame_size.x, 0.0),
+        };
+        let top_left = uv.xy() + frame_offset * t as f32;
+        (top_left, top_left + frame_size).into()
     }
 }
 
@@ -1,4 +1,3 @@
-use crate::graphics::pixel_art::PIXEL_PREC;
 use glam::*;
 
 #[derive(Debug)]
@@ -27,10 +26,11 @@ impl TextureRaw {
         device: &wgpu::Device,
         camera_width: u32,
         camera_height: u32,
+        pixel_prec: u32,
     ) -> Self {
         let size = wgpu::Extent3d {
-            width: camera_width * PIXEL_PREC,
-            height: camera_height * PIXEL_PREC,
+            width: camera_width * pixel_prec,
+            height: camera_height * pixel_prec,
             depth_or_array_layers: 1,
         };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
@@ -1,11 +1,18 @@
 //TODO: god find some other tile editor.
 //TODO: add ids to Layers avoid duplication
 
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
 
-use glam::Vec2;
+use glam::*;
 
-use super::{sprite::Sprite, texture::GPUTexture};
+use super::{atlas::AtlasTexture, quad::QuadInstance, sprite::Sprite};
+use crate::{file_system::LoadError, graphics::new_flip_mask, math::Rect};
+
+/// Width and height of a tile layer chunk, in tiles.
+pub const CHUNK_SIZE: i32 = 16;
 
 /// Describes a tilemap, along with it's tilesets and textures. Can be used to render tiled exports.
 pub struct TileMap {
@@ -13,6 +20,24 @@ pub struct TileMap {
     pub tile_height: u32,
     pub tile_layers: HashMap<String, TileLayer>,
     pub image_layers: HashMap<String, ImageLayer>,
+    pub object_layers: HashMap<String, ObjectLayer>,
+}
+
+impl TileMap {
+    /// Finds an object by the name of its layer and its own name.
+    pub fn object(&self, layer: &str, name: &str) -> Result<&MapObject, LoadError> {
+        self.object_layers
+            .get(layer)
+            .and_then(|object_layer| object_layer.get(name))
+            .ok_or_else(|| LoadError::MissingObject(format!("{}/{}", layer, name)))
+    }
+
+    /// Advances every layer's tile animations.
+    pub fn update(&mut self, delta: f32) {
+        for tile_layer in self.tile_layers.values_mut() {
+            tile_layer.update(delta);
+        }
+    }
 }
 
 pub struct ImageLayer {
@@ -22,62 +47,294 @@ pub struct ImageLayer {
     pub repeat_y: bool,
 }
 
+/// Things placed in the map that aren't tiles, like spawn points, triggers and regions.
+pub struct ObjectLayer {
+    pub name: String,
+    pub objects: Vec<MapObject>,
+}
+
+impl ObjectLayer {
+    /// The first object called `name`.
+    pub fn get(&self, name: &str) -> Option<&MapObject> {
+        self.objects.iter().find(|object| object.name == name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MapObject {
+    pub id: u32,
+    pub name: String,
+    /// Tiled's class (or type) field, which the game can use however it likes.
+    pub kind: String,
+    /// Top left corner for rectangles and ellipses, the first point of polygons.
+    pub position: Vec2,
+    /// Clockwise around `position`, in degrees.
+    pub rotation: f32,
+    pub shape: ObjectShape,
+    pub properties: tiled::Properties,
+}
+
+impl MapObject {
+    /// Converts an object read by tiled, moving it by `offset`.
+    pub fn from_tiled(object: &tiled::ObjectData, offset: Vec2) -> Self {
+        let points =
+            |points: &[(f32, f32)]| points.iter().map(|(x, y)| Vec2::new(*x, *y)).collect();
+        Self {
+            id: object.id(),
+            name: object.name.clone(),
+            kind: object.obj_type.clone(),
+            position: offset + Vec2::new(object.x, object.y),
+            rotation: object.rotation,
+            shape: match &object.shape {
+                tiled::ObjectShape::Point(..) => ObjectShape::Point,
+                tiled::ObjectShape::Rect { width, height } => {
+                    ObjectShape::Rect(Vec2::new(*width, *height))
+                }
+                tiled::ObjectShape::Ellipse { width, height } => {
+                    ObjectShape::Ellipse(Vec2::new(*width, *height))
+                }
+                tiled::ObjectShape::Polygon { points: p } => ObjectShape::Polygon(points(p)),
+                tiled::ObjectShape::Polyline { points: p } => ObjectShape::Polyline(points(p)),
+            },
+            properties: object.properties.clone(),
+        }
+    }
+
+    /// Smallest rectangle covering the object, ignoring rotation. Points have no size.
+    pub fn bounds(&self) -> Rect {
+        let (min, max) = match &self.shape {
+            ObjectShape::Point => (Vec2::ZERO, Vec2::ZERO),
+            ObjectShape::Rect(size) | ObjectShape::Ellipse(size) => (Vec2::ZERO, *size),
+            ObjectShape::Polygon(points) | ObjectShape::Polyline(points) => (
+                points.iter().copied().reduce(Vec2::min).unwrap_or_default(),
+                points.iter().copied().reduce(Vec2::max).unwrap_or_default(),
+            ),
+        };
+        let min = self.position + min;
+        let max = self.position + max;
+        Rect::from_pos(min.x, min.y, max.x, max.y)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape {
+    Point,
+    Rect(Vec2),
+    Ellipse(Vec2),
+    /// Points relative to the object's position. The last one joins back up with the first.
+    Polygon(Vec<Vec2>),
+    /// Points relative to the object's position.
+    Polyline(Vec<Vec2>),
+}
+
 pub struct TileLayer {
     pub name: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
     pub tiles: Vec<Tile>,
-    pub texture: GPUTexture,
-    pub vertex_buffer: wgpu::Buffer,
-    pub instance_buffer: wgpu::Buffer,
-    pub instance_count: usize,
-    pub tile_depth_buffer: wgpu::Buffer,
-    pub tile_depth_bind_group: wgpu::BindGroup,
-}
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct TileInstance {
-    pub offset: [f32; 2],
-    pub flip_mask: i32,
-    pub tile_index: i32,
-}
-
-impl TileInstance {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
-        wgpu::vertex_attr_array![5 => Float32x2, 6 => Sint32, 7 => Sint32];
-
-    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &Self::ATTRIBUTES,
+    /// Every tileset the layer's tiles come from.
+    pub tilesets: Vec<Rc<Tileset>>,
+    /// The tiles split into squares, so ones off camera can be skipped.
+    pub chunks: Vec<TileChunk>,
+    /// Seconds the layer's tile animations have been playing for.
+    pub time: f32,
+    /// Custom properties set on the layer in Tiled.
+    pub properties: tiled::Properties,
+}
+
+/// A square of a tile layer's tiles, skipped as a whole when it's off camera.
+pub struct TileChunk {
+    /// Covers every tile in the chunk, including any sticking out of their cell.
+    pub bounds: Rect,
+    /// Which of the layer's tiles each instance draws.
+    pub tiles: Vec<usize>,
+    /// One per tile, with the depth filled in when the layer is drawn.
+    pub instances: Vec<QuadInstance>,
+    /// Instances of animated tiles, which get their uv rewritten as the animation plays.
+    pub animated: Vec<usize>,
+}
+
+impl TileLayer {
+    /// Splits the tiles into chunks. Has to be called again if the tiles change.
+    pub fn build_chunks(&mut self) {
+        let mut chunks: BTreeMap<(i32, i32), TileChunk> = BTreeMap::new();
+        for (i, tile) in self.tiles.iter().enumerate() {
+            let tileset = &self.tilesets[tile.tileset];
+            let position = self.tile_position(tile);
+            let size = tileset.tile_size().as_vec2();
+            let bounds = Rect {
+                x: position.x,
+                y: position.y,
+                w: size.x,
+                h: size.y,
+            };
+
+            let key = (tile.x.div_euclid(CHUNK_SIZE), tile.y.div_euclid(CHUNK_SIZE));
+            let chunk = chunks.entry(key).or_insert_with(|| TileChunk {
+                bounds,
+                tiles: vec![],
+                instances: vec![],
+                animated: vec![],
+            });
+            chunk.bounds = chunk.bounds.union(&bounds);
+            if tileset.animations.contains_key(&tile.id) {
+                chunk.animated.push(chunk.instances.len());
+            }
+            chunk.tiles.push(i);
+            chunk.instances.push(QuadInstance::new(
+                position.to_array(),
+                size.to_array(),
+                tileset
+                    .tile_atlas_uv(tileset.frame_at(tile.id, self.time))
+                    .to_array(),
+                new_flip_mask(tile.flip_x, tile.flip_y),
+            ));
+        }
+        self.chunks = chunks.into_values().collect();
+    }
+
+    /// Looks up a custom property of a tile. Properties set on the tile in its tileset come
+    /// first, and ones set on the whole layer are used for any the tile doesn't have.
+    pub fn tile_property(&self, tile: &Tile, name: &str) -> Option<&tiled::PropertyValue> {
+        self.tilesets[tile.tileset]
+            .properties
+            .get(&tile.id)
+            .and_then(|properties| properties.get(name))
+            .or_else(|| self.properties.get(name))
+    }
+
+    /// Names of every bool property that's true for a tile, including ones from the layer.
+    pub fn tile_flags(&self, tile: &Tile) -> Vec<&str> {
+        let tile_properties = self.tilesets[tile.tileset].properties.get(&tile.id);
+        let mut names: Vec<&str> = tile_properties
+            .into_iter()
+            .flatten()
+            .chain(&self.properties)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort();
+        names.dedup();
+        names.retain(|name| {
+            self.tile_property(tile, name) == Some(&tiled::PropertyValue::BoolValue(true))
+        });
+        names
+    }
+
+    /// Moves the tile animations on by `delta` seconds.
+    pub fn update(&mut self, delta: f32) {
+        self.time += delta;
+        for chunk in self.chunks.iter_mut() {
+            for &i in chunk.animated.iter() {
+                let tile = &self.tiles[chunk.tiles[i]];
+                let tileset = &self.tilesets[tile.tileset];
+                chunk.instances[i].uv = tileset
+                    .tile_atlas_uv(tileset.frame_at(tile.id, self.time))
+                    .to_array();
+            }
         }
     }
+
+    /// Top left corner of a tile. Like in Tiled, tiles bigger than the map's grid stick out of the
+    /// top and right of their cell.
+    pub fn tile_position(&self, tile: &Tile) -> Vec2 {
+        let cell = IVec2::new(tile.x, tile.y)
+            * IVec2::new(self.tile_width as i32, self.tile_height as i32);
+        let overhang = self.tilesets[tile.tileset].tile_height as i32 - self.tile_height as i32;
+        Vec2::new(cell.x as f32, (cell.y - overhang) as f32)
+    }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Vertex {
-    pub position: [f32; 2],
-    pub uv_mask: i32,
+/// A tileset's image, and how its tiles are laid out in it. Tile ids count left to right, then
+/// top to bottom.
+#[derive(Debug)]
+pub struct Tileset {
+    pub texture: AtlasTexture,
+    pub columns: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Pixels between the image's edge and the first tile.
+    pub margin: u32,
+    /// Pixels between neighbouring tiles.
+    pub spacing: u32,
+    /// Animations of any animated tiles, by tile id.
+    pub animations: HashMap<u32, TileAnimation>,
+    /// Custom properties of any tiles that have them, by tile id.
+    pub properties: HashMap<u32, tiled::Properties>,
+    /// Shapes drawn in Tiled's collision editor, by tile id. Positions are relative to the top
+    /// left of the tile.
+    pub collisions: HashMap<u32, Vec<MapObject>>,
+}
+
+/// The tiles an animated tile cycles through.
+#[derive(Debug, Clone)]
+pub struct TileAnimation {
+    /// Tile ids in the same tileset, and how many seconds each one shows for. Never empty.
+    pub frames: Vec<(u32, f32)>,
 }
 
-impl Vertex {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x2, 1 => Sint32];
+impl TileAnimation {
+    /// The tile showing `time` seconds into the animation, which loops.
+    pub fn frame_at(&self, time: f32) -> u32 {
+        let length: f32 = self.frames.iter().map(|(_, duration)| duration).sum();
+        if length <= 0.0 {
+            return self.frames[0].0;
+        }
+        let mut time = time.rem_euclid(length);
+        for (id, duration) in self.frames.iter() {
+            if time < *duration {
+                return *id;
+            }
+            time -= duration;
+        }
+        self.frames.last().unwrap().0
+    }
+}
 
-    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBUTES,
+impl Tileset {
+    /// The tile drawn in place of `id` after `time` seconds, which is just `id` unless it's
+    /// animated.
+    pub fn frame_at(&self, id: u32, time: f32) -> u32 {
+        match self.animations.get(&id) {
+            Some(animation) => animation.frame_at(time),
+            None => id,
         }
     }
+
+    pub fn tile_size(&self) -> UVec2 {
+        UVec2::new(self.tile_width, self.tile_height)
+    }
+
+    /// Left, top, right and bottom edges of a tile in the tileset's image, from 0 to 1.
+    pub fn tile_uv(&self, id: u32) -> Vec4 {
+        let cell = UVec2::new(id % self.columns, id / self.columns);
+        let top_left = self.margin + cell * (self.tile_size() + self.spacing);
+        let image_size = self.texture.size.as_vec2();
+        let top_left = top_left.as_vec2() / image_size;
+        let bottom_right = top_left + self.tile_size().as_vec2() / image_size;
+        Vec4::new(top_left.x, top_left.y, bottom_right.x, bottom_right.y)
+    }
+
+    /// Same as [`Tileset::tile_uv`], but inside the atlas page the image was packed into.
+    pub fn tile_atlas_uv(&self, id: u32) -> Vec4 {
+        let uv = self.tile_uv(id);
+        let [l, t, r, b] = self.texture.uv.to_array();
+        let (width, height) = (r - l, b - t);
+        Vec4::new(
+            l + width * uv.x,
+            t + height * uv.y,
+            l + width * uv.z,
+            t + height * uv.w,
+        )
+    }
 }
 
 pub struct Tile {
     pub flip_x: bool,
     pub flip_y: bool,
+    /// Local to the tile's tileset.
     pub id: u32,
+    /// Index into the layer's tilesets.
+    pub tileset: usize,
     pub x: i32,
     pub y: i32,
 }
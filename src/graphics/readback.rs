@@ -0,0 +1,133 @@
+//! A texture that can be rendered to and copied back to the CPU, along with the buffer it's
+//! copied into. Both are kept around, so reading back every frame doesn't allocate anything on
+//! the GPU.
+
+use std::{num::NonZeroU32, sync::mpsc};
+
+use image::RgbaImage;
+
+pub struct Readback {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+    /// Rows in a copy have to be aligned, so there may be some padding at the end of each one.
+    padded_bytes_per_row: u32,
+    /// Hears back once the buffer has been mapped, after a copy was submitted.
+    mapping: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+impl Readback {
+    /// `format` has to be 4 bytes per pixel.
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (4 * width).div_ceil(align) * align;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            texture,
+            view,
+            buffer,
+            size,
+            format,
+            padded_bytes_per_row,
+            mapping: None,
+        }
+    }
+
+    /// Copies the texture into the buffer. The buffer can't still be mapped from an earlier copy,
+    /// so that has to be read first.
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.copy_from(encoder, &self.texture);
+    }
+
+    /// Like [`Readback::copy`], for a texture that was rendered somewhere else. It has to be the
+    /// same size and format as this one's, and allow copying from.
+    pub fn copy_from(&self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(self.padded_bytes_per_row),
+                    rows_per_image: NonZeroU32::new(self.size.height),
+                },
+            },
+            self.size,
+        );
+    }
+
+    /// Starts mapping the buffer, once the copy has been submitted.
+    pub fn map(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                sender.send(result).unwrap();
+            });
+        self.mapping = Some(receiver);
+    }
+
+    pub fn is_mapping(&self) -> bool {
+        self.mapping.is_some()
+    }
+
+    /// Waits for the buffer to be mapped and reads it into an image, or `None` if it was never
+    /// mapped.
+    pub fn read(&mut self, device: &wgpu::Device) -> Option<RgbaImage> {
+        let receiver = self.mapping.take()?;
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap().unwrap();
+
+        let bgra = matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        let unpadded_bytes_per_row = 4 * self.size.width;
+        let slice = self.buffer.slice(..);
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * self.size.height) as usize);
+        for row in slice
+            .get_mapped_range()
+            .chunks(self.padded_bytes_per_row as usize)
+        {
+            for pixel in row[..unpadded_bytes_per_row as usize].chunks(4) {
+                if bgra {
+                    pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                } else {
+                    pixels.extend_from_slice(pixel);
+                }
+            }
+        }
+        self.buffer.unmap();
+
+        RgbaImage::from_raw(self.size.width, self.size.height, pixels)
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::VecDeque, fs::File, io::Write};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbaImage,
+};
+
+use crate::file_system::{self, SaveError};
+
+/// Keeps the last `capacity` frames handed to it while recording, along with how long each one
+/// was on screen, so they can be saved as a clip.
+pub struct Recorder {
+    frames: VecDeque<(RgbaImage, f32)>,
+    capacity: usize,
+    recording: bool,
+}
+
+impl Recorder {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            recording: false,
+        }
+    }
+
+    /// Starts a new recording, throwing away any frames from the previous one.
+    pub fn start(&mut self) {
+        self.frames.clear();
+        self.recording = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.recording = false;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Adds a frame rendered `delta` seconds after the previous one. Ignored unless recording.
+    pub fn push(&mut self, frame: RgbaImage, delta: f32) {
+        if !self.recording {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((frame, delta));
+    }
+
+    /// Which frames to keep and how long each is shown for in milliseconds, rounded to
+    /// `resolution` while keeping the total in sync with the time that actually passed. Frames
+    /// that would be shown for less than `min` are dropped, and the one before stays up instead.
+    fn delays(&self, resolution: u32, min: u32) -> Vec<(usize, u32)> {
+        // a frame stays up until the next one arrives, and the last one just reuses its own delta
+        let shown_for = self
+            .frames
+            .iter()
+            .skip(1)
+            .chain(self.frames.back())
+            .map(|(_, delta)| delta);
+
+        let mut delays = vec![];
+        let mut shown = 0;
+        let mut elapsed = 0.0;
+        let mut rounded_elapsed = 0;
+        for (i, delta) in shown_for.enumerate() {
+            elapsed += delta * 1000.0;
+            let rounded = (elapsed / resolution as f32).round() as u32 * resolution;
+            let delay = rounded.saturating_sub(rounded_elapsed);
+            let last = i + 1 == self.frames.len();
+            if delay >= min || last {
+                delays.push((shown, delay.max(min)));
+                rounded_elapsed = rounded;
+                shown = i + 1;
+            }
+        }
+        delays
+    }
+
+    pub fn save_gif(&self, path: &str) -> Result<(), SaveError> {
+        file_system::create_parent_dir(path)?;
+        let file = match File::create(path) {
+            Ok(f) => f,
+            Err(_) => return Err(SaveError::CantWriteFile(path.to_owned())),
+        };
+
+        let mut encoder = GifEncoder::new(file);
+        if encoder.set_repeat(Repeat::Infinite).is_err() {
+            return Err(SaveError::CantSaveImage(path.to_owned()));
+        }
+
+        // gif delays are in hundredths of a second, and browsers slow anything under two of them
+        // down to a tenth of a second
+        let frames = self.delays(10, 20).into_iter().map(|(i, delay)| {
+            let (image, _) = &self.frames[i];
+            Frame::from_parts(image.clone(), 0, 0, Delay::from_numer_denom_ms(delay, 1))
+        });
+        match encoder.encode_frames(frames) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(SaveError::CantSaveImage(path.to_owned())),
+        }
+    }
+
+    /// Saves every frame as `dir/0000.png`, `dir/0001.png`, ... along with `dir/delays.txt`, which
+    /// lists how many milliseconds each frame should be shown for.
+    pub fn save_png_sequence(&self, dir: &str) -> Result<(), SaveError> {
+        let path = format!("{}/delays.txt", dir);
+        file_system::create_parent_dir(&path)?;
+
+        // nothing is dropped here
+        let delays = self.delays(1, 0);
+        for (i, (image, _)) in self.frames.iter().enumerate() {
+            file_system::save_image(&format!("{}/{:04}.png", dir, i), image)?;
+        }
+
+        let contents: String = delays
+            .iter()
+            .map(|(i, delay)| format!("{:04}.png {}\n", i, delay))
+            .collect();
+        match File::create(&path).and_then(|mut f| f.write_all(contents.as_bytes())) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(SaveError::CantWriteFile(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorder(deltas: &[f32]) -> Recorder {
+        let mut recorder = Recorder::new(deltas.len());
+        recorder.start();
+        for delta in deltas {
+            recorder.push(RgbaImage::new(1, 1), *delta);
+        }
+        recorder
+    }
+
+    #[test]
+    fn delays_add_up_to_elapsed_time() {
+        // 60fps alternates between 20 and 30ms, dropping the frames that would only get 10
+        let delays = recorder(&[1.0 / 60.0; 6]).delays(10, 20);
+        assert_eq!(delays, vec![(0, 20), (1, 30), (3, 20), (4, 30)]);
+        assert_eq!(delays.iter().map(|(_, delay)| delay).sum::<u32>(), 100);
+    }
+
+    #[test]
+    fn short_frames_are_merged() {
+        // frame 3 comes 4ms after frame 2, so frame 2 stays up in its place
+        let delays = recorder(&[0.05, 0.05, 0.05, 0.004, 0.05]).delays(10, 20);
+        assert_eq!(delays, vec![(0, 50), (1, 50), (2, 50), (4, 50)]);
+    }
+
+    #[test]
+    fn last_frame_reuses_its_delta() {
+        let delays = recorder(&[0.05, 0.03, 0.07]).delays(1, 0);
+        assert_eq!(delays, vec![(0, 30), (1, 70), (2, 70)]);
+    }
+}
@@ -0,0 +1,400 @@
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use glam::*;
+use image::{Rgba, RgbaImage};
+use itertools::iproduct;
+
+use super::{
+    assets::AssetLoader,
+    linear_to_srgb,
+    pixel_art::{
+        atlas::AtlasTexture,
+        palette::Palette,
+        sprite::Sprite,
+        sprite_sheet::{Orientation, SpriteSheet},
+        tilemap::TileLayer,
+    },
+    srgb_to_linear, DrawJob, DrawParams, DrawQueue,
+};
+
+/// Rasterizes a [`DrawQueue`] on the CPU at the base resolution. This mirrors what the pixel art
+/// shaders do, so frames can be compared pixel for pixel without ever submitting anything to a GPU.
+///
+/// Every atlas texture keeps a CPU copy of its image, which is what gets sampled here. Assets loaded
+/// through this renderer are only kept on the CPU, so it works without a device. Quads placed on
+/// half pixels land exactly between texels, which GPUs are free to round either way, and scaled
+/// or rotated quads can come out a pixel different along texel edges.
+pub struct SoftwareRenderer {
+    pub camera_width: u32,
+    pub camera_height: u32,
+    pub camera: Vec2,
+    /// Indexed by [`PaletteHandle`](super::pixel_art::palette::PaletteHandle), so this should
+    /// be a copy of the renderer's palettes.
+    pub palettes: Vec<Palette>,
+    sprite_ids: Rc<RefCell<HashSet<usize>>>,
+}
+
+/// Quad in screen space, with texture coordinates that are already flipped.
+struct Quad<'a> {
+    image: &'a RgbaImage,
+    position: Vec2,
+    size: Vec2,
+    uv_left: f32,
+    uv_right: f32,
+    uv_top: f32,
+    uv_bottom: f32,
+    depth: f32,
+    /// Off for the translucent pass, which is drawn back to front instead.
+    write_depth: bool,
+    // relative to position
+    pivot: Vec2,
+    scale: Vec2,
+    rotation: Vec2,
+    palette: Option<&'a Palette>,
+    tint: Vec3,
+    alpha: f32,
+    flash_color: Vec3,
+    flash: f32,
+}
+
+impl<'a> Quad<'a> {
+    fn new(image: &'a RgbaImage, position: Vec2, size: Vec2, uvs: Vec4, depth: f32) -> Self {
+        Self {
+            image,
+            position,
+            size,
+            uv_left: uvs.x,
+            uv_right: uvs.y,
+            uv_top: uvs.z,
+            uv_bottom: uvs.w,
+            depth,
+            write_depth: true,
+            pivot: Vec2::ZERO,
+            scale: Vec2::ONE,
+            rotation: Vec2::X,
+            palette: None,
+            tint: Vec3::ONE,
+            alpha: 1.0,
+            flash_color: Vec3::ONE,
+            flash: 0.0,
+        }
+    }
+
+    /// Applies everything in `params` except the position and flips, which are already part of
+    /// the quad.
+    fn with_params(mut self, params: &DrawParams, palette: Option<&'a Palette>) -> Self {
+        self.pivot = params.position - self.position;
+        self.scale = params.scale;
+        self.rotation = params.rotation.cos_sin();
+        self.palette = palette;
+        self.tint = params.tint;
+        self.alpha = params.alpha;
+        self.flash_color = params.flash_color;
+        self.flash = params.flash;
+        self
+    }
+
+    /// Where a point in the quad, from its top left corner, ends up on screen.
+    fn transform(&self, local: Vec2) -> Vec2 {
+        let scaled = (local - self.pivot) * self.scale;
+        self.position + self.pivot + self.rotation.rotate(scaled)
+    }
+
+    /// The inverse of [`Quad::transform`].
+    fn untransform(&self, screen: Vec2) -> Vec2 {
+        let rotated = screen - self.position - self.pivot;
+        let unrotated = Vec2::new(self.rotation.x, -self.rotation.y).rotate(rotated);
+        unrotated / self.scale + self.pivot
+    }
+}
+
+struct Target {
+    width: u32,
+    height: u32,
+    // linear colour, since that's what the GPU blends in
+    color: Vec<Vec3>,
+    depth: Vec<f32>,
+}
+
+impl Target {
+    fn new(width: u32, height: u32) -> Self {
+        let len = (width * height) as usize;
+        Self {
+            width,
+            height,
+            color: vec![Vec3::ZERO; len],
+            depth: vec![1.0; len],
+        }
+    }
+
+    fn draw(&mut self, quad: &Quad) {
+        if quad.scale.x == 0.0 || quad.scale.y == 0.0 {
+            return;
+        }
+
+        let corners = [
+            Vec2::ZERO,
+            quad.size * Vec2::X,
+            quad.size * Vec2::Y,
+            quad.size,
+        ]
+        .map(|corner| quad.transform(corner));
+        let min = corners.into_iter().reduce(Vec2::min).unwrap();
+        let max = corners.into_iter().reduce(Vec2::max).unwrap();
+
+        // a pixel is covered when its center is inside the quad
+        let first = (min - 0.5).ceil();
+        let last = (max - 0.5).ceil();
+        let x_range = first.x.max(0.0) as u32..last.x.clamp(0.0, self.width as f32) as u32;
+        let y_range = first.y.max(0.0) as u32..last.y.clamp(0.0, self.height as f32) as u32;
+        let (image_width, image_height) = quad.image.dimensions();
+
+        for (y, x) in iproduct!(y_range, x_range) {
+            let i = (y * self.width + x) as usize;
+            if quad.depth > self.depth[i] {
+                continue;
+            }
+
+            let local = quad.untransform(Vec2::new(x as f32, y as f32) + 0.5);
+            let t = local / quad.size;
+            if t.cmplt(Vec2::ZERO).any() || t.cmpge(Vec2::ONE).any() {
+                continue;
+            }
+
+            let u = quad.uv_left + t.x * (quad.uv_right - quad.uv_left);
+            let v = quad.uv_top + t.y * (quad.uv_bottom - quad.uv_top);
+            let texel_x = ((u * image_width as f32).floor() as u32).min(image_width - 1);
+            let texel_y = ((v * image_height as f32).floor() as u32).min(image_height - 1);
+            let mut texel = quad.image.get_pixel(texel_x, texel_y).0;
+            if let Some(palette) = quad.palette {
+                texel = palette.apply(texel);
+            }
+            let [r, g, b, a] = texel;
+
+            let src = Vec3::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b));
+            let src = (src * quad.tint).lerp(quad.flash_color, quad.flash);
+            let alpha = a as f32 / 255.0 * quad.alpha;
+            if alpha == 0.0 {
+                continue;
+            }
+            self.color[i] = src * alpha + self.color[i] * (1.0 - alpha);
+            if quad.write_depth {
+                self.depth[i] = quad.depth;
+            }
+        }
+    }
+
+    fn into_image(self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let c = self.color[(y * self.width + x) as usize];
+            Rgba([
+                linear_to_srgb(c.x),
+                linear_to_srgb(c.y),
+                linear_to_srgb(c.z),
+                255,
+            ])
+        })
+    }
+}
+
+impl SoftwareRenderer {
+    pub fn new(camera_width: u32, camera_height: u32) -> Self {
+        Self {
+            camera_width,
+            camera_height,
+            camera: Vec2::ZERO,
+            palettes: vec![],
+            sprite_ids: Rc::new(RefCell::new(HashSet::new())),
+        }
+    }
+
+    pub fn update_camera(&mut self, offset: Vec2) {
+        self.camera = offset;
+    }
+
+    fn palette(&self, params: &DrawParams) -> Option<&Palette> {
+        params.palette.and_then(|p| self.palettes.get(p.0 as usize))
+    }
+
+    pub fn render(&self, dq: DrawQueue) -> RgbaImage {
+        // opaque jobs are drawn in queue order, which the depth test makes irrelevant, and
+        // translucent ones after them, back to front
+        let mut opaque: Vec<(f32, DrawJob)> = vec![];
+        let mut translucent: Vec<(f32, DrawJob)> = vec![];
+        let mut depth = 1.0;
+        let jobs = dq.into_sorted();
+        let depth_step = 1.0 / (jobs.len() as f32);
+        for job in jobs.iter() {
+            match job {
+                DrawJob::Sprite(sprite, params) => {
+                    let (sprite, params) = params.prerotated(*sprite, &sprite.rotations);
+                    let pass = if params.is_translucent(&sprite.texture, &self.palettes) {
+                        &mut translucent
+                    } else {
+                        &mut opaque
+                    };
+                    pass.push((depth, DrawJob::Sprite(sprite, params)));
+                }
+                DrawJob::SpriteSheet(sprite_sheet, t, params) => {
+                    let (sprite_sheet, params) =
+                        params.prerotated(*sprite_sheet, &sprite_sheet.rotations);
+                    let pass = if params.is_translucent(&sprite_sheet.texture, &self.palettes) {
+                        &mut translucent
+                    } else {
+                        &mut opaque
+                    };
+                    pass.push((depth, DrawJob::SpriteSheet(sprite_sheet, *t, params)));
+                }
+                DrawJob::TileLayer(tile_layer) => {
+                    // a layer can mix opaque and translucent tilesets, each pass draws its own
+                    let tilesets = &tile_layer.tilesets;
+                    if tilesets.iter().any(|t| t.texture.translucent) {
+                        translucent.push((depth, DrawJob::TileLayer(tile_layer)));
+                    }
+                    if tilesets.iter().any(|t| !t.texture.translucent) {
+                        opaque.push((depth, DrawJob::TileLayer(tile_layer)));
+                    }
+                }
+            }
+            depth -= depth_step;
+        }
+
+        let mut target = Target::new(self.camera_width, self.camera_height);
+
+        for (depth, job) in opaque {
+            self.draw_job(&mut target, job, depth, true);
+        }
+
+        // bigger depths are further back
+        translucent.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        for (depth, job) in translucent {
+            self.draw_job(&mut target, job, depth, false);
+        }
+
+        target.into_image()
+    }
+
+    fn draw_job(&self, target: &mut Target, job: DrawJob, depth: f32, write_depth: bool) {
+        match job {
+            DrawJob::Sprite(sprite, params) => {
+                self.draw_sprite(target, sprite, &params, depth, write_depth)
+            }
+            DrawJob::SpriteSheet(sprite_sheet, t, params) => {
+                self.draw_sprite_sheet(target, sprite_sheet, t, &params, depth, write_depth)
+            }
+            DrawJob::TileLayer(tile_layer) => {
+                self.draw_tile_layer(target, tile_layer, depth, write_depth)
+            }
+        }
+    }
+
+    fn draw_sprite(
+        &self,
+        target: &mut Target,
+        sprite: &Sprite,
+        params: &DrawParams,
+        depth: f32,
+        write_depth: bool,
+    ) {
+        let image = &sprite.texture.image;
+        let size = sprite.texture.size.as_vec2();
+        let position = params.offset(size, sprite.origin());
+        let uvs = Vec4::new(
+            if params.flip_x { 1.0 } else { 0.0 },
+            if params.flip_x { 0.0 } else { 1.0 },
+            if params.flip_y { 1.0 } else { 0.0 },
+            if params.flip_y { 0.0 } else { 1.0 },
+        );
+        let mut quad = Quad {
+            write_depth,
+            ..Quad::new(image, position, size, uvs, depth)
+        }
+        .with_params(params, self.palette(params));
+        if !params.camera_locked {
+            quad.position -= self.camera;
+        }
+        target.draw(&quad);
+    }
+
+    fn draw_tile_layer(
+        &self,
+        target: &mut Target,
+        tile_layer: &TileLayer,
+        depth: f32,
+        write_depth: bool,
+    ) {
+        for tile in tile_layer.tiles.iter() {
+            let tileset = &tile_layer.tilesets[tile.tileset];
+            // opaque and translucent tiles are drawn in different passes
+            if tileset.texture.translucent == write_depth {
+                continue;
+            }
+            let size = tileset.tile_size().as_vec2();
+            let id = tileset.frame_at(tile.id, tile_layer.time);
+            let [left, top, right, bottom] = tileset.tile_uv(id).to_array();
+            let uvs = Vec4::new(
+                if tile.flip_x { right } else { left },
+                if tile.flip_x { left } else { right },
+                if tile.flip_y { bottom } else { top },
+                if tile.flip_y { top } else { bottom },
+            );
+            let position = tile_layer.tile_position(tile) - self.camera;
+            target.draw(&Quad {
+                write_depth,
+                ..Quad::new(&tileset.texture.image, position, size, uvs, depth)
+            });
+        }
+    }
+
+    fn draw_sprite_sheet(
+        &self,
+        target: &mut Target,
+        sprite_sheet: &SpriteSheet,
+        t: u32,
+        params: &DrawParams,
+        depth: f32,
+        write_depth: bool,
+    ) {
+        let image = &sprite_sheet.texture.image;
+        let size = sprite_sheet.size().as_vec2();
+        let count = sprite_sheet.count as f32;
+        let (mut left, mut right, mut top, mut bottom) = (0.0, 1.0, 0.0, 1.0);
+        let frame_start = t as f32 / count;
+        match sprite_sheet.orientation {
+            Orientation::Horizontal => {
+                left = frame_start;
+                right = frame_start + 1.0 / count;
+            }
+            Orientation::Vertical => {
+                top = frame_start;
+                bottom = frame_start + 1.0 / count;
+            }
+        }
+        let position = params.offset(size, sprite_sheet.origin());
+        let uvs = Vec4::new(
+            if params.flip_x { right } else { left },
+            if params.flip_x { left } else { right },
+            if params.flip_y { bottom } else { top },
+            if params.flip_y { top } else { bottom },
+        );
+        let mut quad = Quad {
+            write_depth,
+            ..Quad::new(image, position, size, uvs, depth)
+        }
+        .with_params(params, self.palette(params));
+        if !params.camera_locked {
+            quad.position -= self.camera;
+        }
+        target.draw(&quad);
+    }
+}
+
+impl AssetLoader for SoftwareRenderer {
+    fn texture(&self, image: RgbaImage) -> AtlasTexture {
+        AtlasTexture::cpu_only(image)
+    }
+
+    fn sprite_ids(&self) -> &Rc<RefCell<HashSet<usize>>> {
+        &self.sprite_ids
+    }
+}
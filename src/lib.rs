@@ -7,14 +7,20 @@ pub mod player;
 use crate::{
     file_system::LoadError,
     graphics::{
+        assets::AssetLoader,
         pixel_art::{sprite::*, sprite_sheet::*, tilemap::*},
-        DrawParams, DrawQueue, Renderer,
+        recorder::Recorder,
+        DrawParams, DrawQueue, Layer, Renderer,
     },
     input::*,
-    math::Rect,
+    math::{polygon_contains, Rect},
     player::Player,
 };
 use glam::*;
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::TAU,
+};
 use winit::window::Window;
 use winit::{
     dpi::PhysicalSize,
@@ -38,6 +44,8 @@ mod boss {
     use super::*;
     pub struct Boss {
         pub sprites: Sprites,
+        /// Bottom middle of the body, the other parts are drawn relative to it.
+        pub position: Vec2,
     }
 
     pub struct Sprites {
@@ -63,6 +71,9 @@ pub struct Game {
     pub environment: Environment,
     pub tile_map: TileMap,
     pub level_geometry: LevelGeometry,
+    pub recorder: Recorder,
+    /// How long the frame being captured took, since it's only read back a frame later.
+    pub captured_delta: f32,
 }
 
 pub struct Environment {
@@ -71,15 +82,232 @@ pub struct Environment {
     pub platforms: Sprite,
 }
 
+/// What the level's tiles do, worked out from their custom properties in Tiled. Bool properties
+/// set on a tile in its tileset, or on a whole tile layer, give it a kind:
+/// - `solid`: blocks movement from every side.
+/// - `one_way`: can be stood on, but jumped up through.
+/// - `ladder`: can be climbed. The top of a ladder can be stood on.
+/// - `hazard`: hurts whatever touches it.
+/// - anything else ends up in `tagged`, under the property's name.
+///
+/// A `slope` string property of `left` or `right` makes the tile a slope that's high on that side,
+/// which the player stands on and walks up and down.
+///
+/// Tiles with shapes drawn in Tiled's collision editor collide with those instead of the whole
+/// tile, except for ladders, which are always a strip up the middle.
 pub struct LevelGeometry {
-    pub blocks: Vec<Rect>,
+    pub blocks: Vec<Collider>,
+    pub one_ways: Vec<Collider>,
     pub ladders: Vec<Rect>,
     pub top_ladders: Vec<Rect>,
+    pub hazards: Vec<Collider>,
+    pub slopes: Vec<Slope>,
+    pub tagged: HashMap<String, Vec<Collider>>,
+}
+
+/// Part of the level something can bump into, in world space.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Collider {
+    Rect(Rect),
+    /// Can be concave. The last point joins back up with the first.
+    Polygon(Vec<Vec2>),
+    /// Only the lines collide, not whatever they go around.
+    Polyline(Vec<Vec2>),
+}
+
+impl Collider {
+    /// Whether it overlaps `rect`. Only touching doesn't count, same as [`Rect::contains`].
+    pub fn overlaps(&self, rect: &Rect) -> bool {
+        let edges_overlap = |points: &[Vec2]| {
+            points
+                .windows(2)
+                .any(|edge| rect.overlaps_segment(edge[0], edge[1]))
+        };
+        match self {
+            Collider::Rect(r) => r.contains(rect),
+            Collider::Polygon(points) => {
+                let center = Vec2::new(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0);
+                edges_overlap(points)
+                    || points.len() > 2
+                        && rect.overlaps_segment(points[points.len() - 1], points[0])
+                    || polygon_contains(points, center)
+            }
+            Collider::Polyline(points) => edges_overlap(points),
+        }
+    }
+
+    /// Smallest rectangle covering it.
+    pub fn bounds(&self) -> Rect {
+        match self {
+            Collider::Rect(r) => *r,
+            Collider::Polygon(points) | Collider::Polyline(points) => {
+                let min = points.iter().copied().reduce(Vec2::min).unwrap_or_default();
+                let max = points.iter().copied().reduce(Vec2::max).unwrap_or_default();
+                Rect::from_pos(min.x, min.y, max.x, max.y)
+            }
+        }
+    }
+
+    /// The shapes in a tile's collision editor, placed where the tile is and flipped along with
+    /// it, or `None` if it doesn't have any. Ellipses are turned into polygons and points are
+    /// skipped.
+    pub fn from_tile(tile_layer: &TileLayer, tile: &Tile) -> Option<Vec<Self>> {
+        let tileset = &tile_layer.tilesets[tile.tileset];
+        let objects = tileset.collisions.get(&tile.id)?;
+        let size = tileset.tile_size().as_vec2();
+        let origin = tile_layer.tile_position(tile);
+        let place = |p: Vec2| {
+            let x = if tile.flip_x { size.x - p.x } else { p.x };
+            let y = if tile.flip_y { size.y - p.y } else { p.y };
+            origin + Vec2::new(x, y)
+        };
+
+        let colliders = objects
+            .iter()
+            .filter_map(|object| {
+                let rotation = Vec2::from_angle(object.rotation.to_radians());
+                let transform = |p: Vec2| place(object.position + rotation.rotate(p));
+                Some(match &object.shape {
+                    ObjectShape::Point => return None,
+                    ObjectShape::Rect(size) if object.rotation == 0.0 => {
+                        let a = transform(Vec2::ZERO);
+                        let b = transform(*size);
+                        let (min, max) = (a.min(b), a.max(b));
+                        Collider::Rect(Rect::from_pos(min.x, min.y, max.x, max.y))
+                    }
+                    ObjectShape::Rect(size) => Collider::Polygon(
+                        [Vec2::ZERO, *size * Vec2::X, *size, *size * Vec2::Y]
+                            .map(transform)
+                            .to_vec(),
+                    ),
+                    ObjectShape::Ellipse(size) => {
+                        let radius = *size / 2.0;
+                        Collider::Polygon(
+                            (0..ELLIPSE_SEGMENTS)
+                                .map(|i| {
+                                    let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * TAU;
+                                    transform(radius + radius * Vec2::from_angle(angle))
+                                })
+                                .collect(),
+                        )
+                    }
+                    ObjectShape::Polygon(points) => {
+                        Collider::Polygon(points.iter().map(|p| transform(*p)).collect())
+                    }
+                    ObjectShape::Polyline(points) => {
+                        Collider::Polyline(points.iter().map(|p| transform(*p)).collect())
+                    }
+                })
+            })
+            .collect();
+        Some(colliders)
+    }
+}
+
+/// Sides of the polygons ellipse collision shapes are turned into.
+const ELLIPSE_SEGMENTS: u32 = 16;
+
+/// A tile with a diagonal floor running corner to corner.
+#[derive(Debug, Clone, Copy)]
+pub struct Slope {
+    pub rect: Rect,
+    /// Whether the floor is higher on the right.
+    pub rising: bool,
+}
+
+impl Slope {
+    /// Height of the floor at `x`, clamped to the tile's edges.
+    pub fn floor_at(&self, x: f32) -> f32 {
+        let t = ((x - self.rect.left()) / self.rect.w).clamp(0.0, 1.0);
+        if self.rising {
+            self.rect.bottom() - t * self.rect.h
+        } else {
+            self.rect.top() + t * self.rect.h
+        }
+    }
 }
 
 impl LevelGeometry {
+    pub fn from_tile_map(tile_map: &TileMap) -> Self {
+        let mut geometry = Self {
+            blocks: vec![],
+            one_ways: vec![],
+            ladders: vec![],
+            top_ladders: vec![],
+            hazards: vec![],
+            slopes: vec![],
+            tagged: HashMap::new(),
+        };
+        let (tile_width, tile_height) = (tile_map.tile_width as f32, tile_map.tile_height as f32);
+        let ladder_width = 8.0;
+        let mut ladder_cells = HashSet::new();
+
+        // sorted so the geometry comes out the same every time
+        let mut tile_layers: Vec<&TileLayer> = tile_map.tile_layers.values().collect();
+        tile_layers.sort_by(|a, b| a.name.cmp(&b.name));
+        for tile_layer in tile_layers {
+            for tile in tile_layer.tiles.iter() {
+                // where the tile is drawn, which sticks out of its cell if it's bigger than the grid
+                let position = tile_layer.tile_position(tile);
+                let size = tile_layer.tilesets[tile.tileset].tile_size().as_vec2();
+                let rect = Rect {
+                    x: position.x,
+                    y: position.y,
+                    w: size.x,
+                    h: size.y,
+                };
+                let colliders = Collider::from_tile(tile_layer, tile)
+                    .unwrap_or_else(|| vec![Collider::Rect(rect)]);
+                for flag in tile_layer.tile_flags(tile) {
+                    match flag {
+                        "solid" => geometry.blocks.extend(colliders.iter().cloned()),
+                        "one_way" => geometry.one_ways.extend(colliders.iter().cloned()),
+                        "ladder" => {
+                            geometry.ladders.push(Rect {
+                                x: rect.x + (rect.w - ladder_width) / 2.0,
+                                w: ladder_width,
+                                ..rect
+                            });
+                            ladder_cells.insert((tile.x, tile.y));
+                        }
+                        "hazard" => geometry.hazards.extend(colliders.iter().cloned()),
+                        _ => geometry
+                            .tagged
+                            .entry(flag.to_owned())
+                            .or_default()
+                            .extend(colliders.iter().cloned()),
+                    }
+                }
+                if let Some(tiled::PropertyValue::StringValue(side)) =
+                    tile_layer.tile_property(tile, "slope")
+                {
+                    geometry.slopes.push(Slope {
+                        rect,
+                        rising: side == "right",
+                    });
+                }
+            }
+        }
+
+        // the top of a ladder is the tile without any ladder above it
+        let mut ladder_cells: Vec<(i32, i32)> = ladder_cells.iter().copied().collect();
+        ladder_cells.sort();
+        geometry.top_ladders = ladder_cells
+            .iter()
+            .filter(|(x, y)| ladder_cells.binary_search(&(*x, y - 1)).is_err())
+            .map(|(x, y)| Rect {
+                x: tile_width * *x as f32,
+                y: tile_height * *y as f32,
+                w: tile_width,
+                h: tile_height,
+            })
+            .collect();
+
+        geometry
+    }
+
     pub fn colliding(&self, rect: Rect) -> bool {
-        self.blocks.iter().any(|r| r.contains(&rect))
+        self.blocks.iter().any(|c| c.overlaps(&rect))
     }
 
     pub fn ladder_colliding(&self, rect: Rect) -> bool {
@@ -90,11 +318,35 @@ impl LevelGeometry {
         self.top_ladders.iter().any(|r| r.contains(&rect))
     }
 
-    pub fn collisions(&self, rect: Rect) -> Vec<Rect> {
+    /// Anything that can be stood on but not bumped into, so one way platforms and the tops of
+    /// ladders.
+    pub fn platform_colliding(&self, rect: Rect) -> bool {
+        self.top_ladder_colliding(rect) || self.one_ways.iter().any(|c| c.overlaps(&rect))
+    }
+
+    pub fn hazard_colliding(&self, rect: Rect) -> bool {
+        self.hazards.iter().any(|c| c.overlaps(&rect))
+    }
+
+    pub fn tagged_colliding(&self, tag: &str, rect: Rect) -> bool {
+        self.tagged
+            .get(tag)
+            .is_some_and(|colliders| colliders.iter().any(|c| c.overlaps(&rect)))
+    }
+
+    pub fn slope_collisions(&self, rect: Rect) -> Vec<Slope> {
+        self.slopes
+            .iter()
+            .filter(|slope| slope.rect.contains(&rect))
+            .copied()
+            .collect()
+    }
+
+    pub fn collisions(&self, rect: Rect) -> Vec<Collider> {
         self.blocks
             .iter()
-            .filter(|r| r.contains(&rect))
-            .map(|r| *r)
+            .filter(|c| c.overlaps(&rect))
+            .cloned()
             .collect()
     }
 
@@ -118,6 +370,20 @@ impl LevelGeometry {
 pub const CAMERA_WIDTH: u32 = 427;
 pub const CAMERA_HEIGHT: u32 = 240;
 
+/// Saves the window, supersampled and base resolution frames to `./screenshots/`.
+pub const SCREENSHOT_KEY: VirtualKeyCode = VirtualKeyCode::F12;
+/// Starts recording, and when pressed again saves the clip as a gif to `./screenshots/`.
+pub const RECORD_KEY: VirtualKeyCode = VirtualKeyCode::F9;
+/// Cycles through the downscale filters, to compare them in game.
+pub const FILTER_KEY: VirtualKeyCode = VirtualKeyCode::F2;
+/// About 6 seconds at 60fps.
+pub const RECORDING_CAPACITY: usize = 360;
+
+/// The boss' parts, in between the level and everything that moves.
+pub const BOSS_LAYER: Layer = Layer(10);
+/// The player and anything else that moves around, y-sorted.
+pub const ENTITY_LAYER: Layer = Layer(20);
+
 impl Game {
     pub async fn new(window: &Window) -> Result<Game, LoadError> {
         let mut r = Renderer::new(window, CAMERA_WIDTH, CAMERA_HEIGHT).await;
@@ -136,7 +402,9 @@ impl Game {
             player_heart_half: r.load_sprite(Origin::TopLeft, "ui/player_heart_half.png")?,
         };
 
-        let player = Player::new(&mut r)?;
+        let tile_map = r.load_tilemap("tiles/untitled.tmx")?;
+
+        let player = Player::new(&mut r, tile_map.object("Player", "Spawn")?.position)?;
 
         let sprites = boss::Sprites {
             awake: r.load_sprite(Origin::BottomMiddle, "twelve_string/awake.png")?,
@@ -171,61 +439,13 @@ impl Game {
             sleep: r.load_sprite(Origin::BottomMiddle, "twelve_string/sleep.png")?,
         };
 
-        let boss = Boss { sprites };
-
-        let tile_map = r.load_tilemap("tiles/untitled.tmx")?;
-
-        let blocks = tile_map
-            .tile_layers
-            .get("Inter")
-            .unwrap()
-            .tiles
-            .iter()
-            .map(|Tile { x, y, .. }| Rect {
-                x: (tile_map.tile_width as i32 * (*x)) as f32,
-                y: (tile_map.tile_height as i32 * (*y)) as f32,
-                w: (tile_map.tile_width) as f32,
-                h: (tile_map.tile_height) as f32,
-            })
-            .collect();
-        let ladder_width = 8.0;
-        let ladder_tiles = &tile_map.tile_layers.get("Ladders").unwrap().tiles;
-        let ladders: Vec<Rect> = ladder_tiles
-            .iter()
-            .map(|Tile { x, y, .. }| Rect {
-                x: (tile_map.tile_width as f32) * (*x as f32 + 0.5) - ladder_width / 2.0,
-                y: (tile_map.tile_height as i32 * (*y)) as f32,
-                w: ladder_width,
-                h: (tile_map.tile_height) as f32,
-            })
-            .collect();
-
-        //TODO: this won't work for ladders on the same axis
-        let top_ladders = ladder_tiles
-            .iter()
-            .enumerate()
-            .filter(|(i, ladder_tile)| {
-                if *i == 0 {
-                    true
-                } else {
-                    let prev_tile = ladder_tiles.get(i - 1).unwrap();
-                    prev_tile.y + 1 != ladder_tile.y || prev_tile.x != ladder_tile.x
-                }
-            })
-            .map(|(_, Tile { x, y, .. })| Rect {
-                x: (tile_map.tile_width as i32 * x) as f32,
-                y: (tile_map.tile_height as i32 * y) as f32,
-                w: (tile_map.tile_width) as f32,
-                h: (tile_map.tile_height) as f32,
-            })
-            .collect();
-
-        let level_geometry = LevelGeometry {
-            blocks,
-            ladders,
-            top_ladders,
+        let boss = Boss {
+            sprites,
+            position: tile_map.object("Boss", "Spawn")?.position,
         };
 
+        let level_geometry = LevelGeometry::from_tile_map(&tile_map);
+
         Ok(Self {
             renderer: r,
             player,
@@ -234,6 +454,8 @@ impl Game {
             boss,
             tile_map,
             level_geometry,
+            recorder: Recorder::new(RECORDING_CAPACITY),
+            captured_delta: 0.0,
             previous_time: 0.0,
             current_time: 0.0,
             delta_time: 0.0,
@@ -243,7 +465,9 @@ impl Game {
     pub fn input(&mut self) {}
 
     pub fn update(&mut self, input: &Input, delta: f32) {
+        self.delta_time = delta;
         self.player.update(delta, input, &self.level_geometry);
+        self.tile_map.update(delta);
 
         self.renderer.update_camera(
             self.player.position - UVec2::new(CAMERA_WIDTH / 2, CAMERA_HEIGHT / 2).as_vec2(),
@@ -252,9 +476,11 @@ impl Game {
 
     pub fn draw(&mut self) {
         let mut dq = DrawQueue::new();
+        dq.y_sort(ENTITY_LAYER);
 
         // DRAW WORLD
         {
+            dq.set_layer(Layer::BACKGROUND);
             dq.sprite(
                 &self.environment.moon,
                 DrawParams::from_pos(Vec2::new(320.0, 59.0)),
@@ -265,6 +491,8 @@ impl Game {
             dq.tile_image(&self.tile_map, "Clouds");
             dq.tile_image(&self.tile_map, "Moon");
             dq.tile_layer(&self.tile_map, "Mountains");
+
+            dq.set_layer(Layer::WORLD);
             dq.tile_layer(&self.tile_map, "Cave");
             dq.tile_layer(&self.tile_map, "Graves");
             dq.tile_layer(&self.tile_map, "Inter");
@@ -276,44 +504,49 @@ impl Game {
         // DRAW CHARACTERS
         {
             // draw twelve string
+            dq.set_layer(BOSS_LAYER);
             dq.sheet(
                 &self.boss.sprites.idle_body,
                 0,
                 DrawParams {
-                    position: Vec2::new(218.0, 180.0),
+                    position: self.boss.position,
                     flip_x: false,
                     flip_y: false,
                     camera_locked: false,
+                    ..Default::default()
                 },
             );
             dq.sheet(
                 &self.boss.sprites.idle_arms_bottom,
                 0,
                 DrawParams {
-                    position: Vec2::new(218.0, 55.0),
+                    position: self.boss.position + Vec2::new(0.0, -125.0),
                     flip_x: false,
                     flip_y: false,
                     camera_locked: false,
+                    ..Default::default()
                 },
             );
             dq.sheet(
                 &self.boss.sprites.idle_arms_top,
                 0,
                 DrawParams {
-                    position: Vec2::new(218.0, 132.0),
+                    position: self.boss.position + Vec2::new(0.0, -48.0),
                     flip_x: false,
                     flip_y: false,
                     camera_locked: false,
+                    ..Default::default()
                 },
             );
             dq.sheet(
                 &self.boss.sprites.idle_head,
                 0,
                 DrawParams {
-                    position: Vec2::new(218.0, 82.0),
+                    position: self.boss.position + Vec2::new(0.0, -98.0),
                     flip_x: false,
                     flip_y: false,
                     camera_locked: false,
+                    ..Default::default()
                 },
             );
 
@@ -323,6 +556,7 @@ impl Game {
 
         // DRAW UI
         {
+            dq.set_layer(Layer::UI);
             let player_base_x = 13.0;
             let player_base_y = 11.0;
             dq.sprite(
@@ -376,7 +610,33 @@ impl Game {
             }
         }
 
+        if self.recorder.is_recording() {
+            self.renderer.capture_next_frame();
+        }
         self.renderer.render(dq);
+
+        if let Some(frame) = self.renderer.take_captured_frame() {
+            self.recorder.push(frame, self.captured_delta);
+        }
+        self.captured_delta = self.delta_time;
+    }
+
+    pub fn toggle_recording(&mut self) {
+        if !self.recorder.is_recording() {
+            self.recorder.start();
+            return;
+        }
+
+        self.recorder.stop();
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = file_system::to_screenshot_path(&format!("{}_clip.gif", stamp));
+        match self.recorder.save_gif(&path) {
+            Ok(()) => log::info!("saved recording to {}", path),
+            Err(e) => log::warn!("couldn't save recording: {:?}", e),
+        }
     }
 }
 
@@ -406,7 +666,24 @@ pub async fn run() {
             window_id,
         } if window_id == window.id() => match event {
             WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-            WindowEvent::KeyboardInput { input, .. } => keyboard_inputs.push(*input),
+            WindowEvent::KeyboardInput { input, .. } => {
+                if input.state == ElementState::Pressed
+                    && input.virtual_keycode == Some(SCREENSHOT_KEY)
+                {
+                    game.renderer.screenshot(true);
+                }
+                if input.state == ElementState::Pressed && input.virtual_keycode == Some(RECORD_KEY)
+                {
+                    game.toggle_recording();
+                }
+                if input.state == ElementState::Pressed && input.virtual_keycode == Some(FILTER_KEY)
+                {
+                    let filter = game.renderer.downscale_filter().next();
+                    game.renderer.set_downscale_filter(filter);
+                    log::info!("downscale filter: {:?}", filter);
+                }
+                keyboard_inputs.push(*input);
+            }
             WindowEvent::Resized(physical_size) => {
                 game.renderer.resize(*physical_size);
             }
@@ -432,3 +709,130 @@ pub async fn run() {
         _ => {}
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::{pixel_art::atlas::AtlasTexture, software::SoftwareRenderer};
+    use std::rc::Rc;
+
+    #[test]
+    fn touching_colliders_dont_overlap() {
+        let block = Collider::Rect(Rect::from_pos(0.0, 0.0, 16.0, 16.0));
+        assert!(!block.overlaps(&Rect::from_pos(16.0, 0.0, 32.0, 16.0)));
+        assert!(block.overlaps(&Rect::from_pos(15.0, 0.0, 32.0, 16.0)));
+
+        let slope = Collider::Polygon(vec![
+            Vec2::new(0.0, 16.0),
+            Vec2::new(16.0, 0.0),
+            Vec2::new(16.0, 16.0),
+        ]);
+        // right up against the diagonal, and on the far side of it
+        assert!(!slope.overlaps(&Rect::from_pos(0.0, 0.0, 8.0, 8.0)));
+        assert!(!slope.overlaps(&Rect::from_pos(0.0, 0.0, 4.0, 4.0)));
+        assert!(slope.overlaps(&Rect::from_pos(0.0, 0.0, 9.0, 9.0)));
+    }
+
+    #[test]
+    fn rect_inside_polygon_overlaps() {
+        // none of the edges cross the rect, so only the inside test can catch it
+        let polygon = Collider::Polygon(vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(40.0, 0.0),
+            Vec2::new(40.0, 40.0),
+            Vec2::new(0.0, 40.0),
+        ]);
+        assert!(polygon.overlaps(&Rect::from_pos(10.0, 10.0, 20.0, 20.0)));
+        assert!(!polygon.overlaps(&Rect::from_pos(50.0, 10.0, 60.0, 20.0)));
+
+        let polyline = Collider::Polyline(vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(40.0, 0.0),
+            Vec2::new(40.0, 40.0),
+        ]);
+        assert!(!polyline.overlaps(&Rect::from_pos(10.0, 10.0, 20.0, 20.0)));
+    }
+
+    #[test]
+    fn flipped_tile_collision_rect() {
+        // the bottom half of the tile
+        let bottom_half = MapObject {
+            id: 1,
+            name: String::new(),
+            kind: String::new(),
+            position: Vec2::new(0.0, 8.0),
+            rotation: 0.0,
+            shape: ObjectShape::Rect(Vec2::new(16.0, 8.0)),
+            properties: Default::default(),
+        };
+        let tileset = Tileset {
+            texture: AtlasTexture::cpu_only(image::RgbaImage::new(16, 16)),
+            columns: 1,
+            tile_width: 16,
+            tile_height: 16,
+            margin: 0,
+            spacing: 0,
+            animations: HashMap::new(),
+            properties: HashMap::new(),
+            collisions: HashMap::from([(0, vec![bottom_half])]),
+        };
+        let tile = |flip_x, flip_y| Tile {
+            flip_x,
+            flip_y,
+            id: 0,
+            tileset: 0,
+            x: 2,
+            y: 3,
+        };
+        let tile_layer = TileLayer {
+            name: String::new(),
+            tile_width: 16,
+            tile_height: 16,
+            tiles: vec![],
+            tilesets: vec![Rc::new(tileset)],
+            chunks: vec![],
+            time: 0.0,
+            properties: Default::default(),
+        };
+
+        let colliders = |tile| Collider::from_tile(&tile_layer, &tile).unwrap();
+        assert_eq!(
+            colliders(tile(false, false)),
+            vec![Collider::Rect(Rect::from_pos(32.0, 56.0, 48.0, 64.0))]
+        );
+        // flipping vertically moves it to the top half
+        assert_eq!(
+            colliders(tile(false, true)),
+            vec![Collider::Rect(Rect::from_pos(32.0, 48.0, 48.0, 56.0))]
+        );
+        // and flipping horizontally doesn't move a full width rect
+        assert_eq!(
+            colliders(tile(true, true)),
+            vec![Collider::Rect(Rect::from_pos(32.0, 48.0, 48.0, 56.0))]
+        );
+    }
+
+    #[test]
+    fn infinite_map_chunks_at_negative_coords() {
+        let renderer = SoftwareRenderer::new(CAMERA_WIDTH, CAMERA_HEIGHT);
+        let tile_map = renderer.load_tilemap("tiles/infinite.tmx").unwrap();
+
+        // one chunk ends just above and left of the origin, the other starts at it
+        let tiles: Vec<(i32, i32)> = tile_map.tile_layers["Ground"]
+            .tiles
+            .iter()
+            .map(|tile| (tile.x, tile.y))
+            .collect();
+        assert_eq!(tiles, vec![(-1, -1), (0, 0), (1, 0)]);
+
+        let geometry = LevelGeometry::from_tile_map(&tile_map);
+        assert_eq!(
+            geometry.blocks,
+            vec![
+                Collider::Rect(Rect::from_pos(-16.0, -16.0, 0.0, 0.0)),
+                Collider::Rect(Rect::from_pos(0.0, 0.0, 16.0, 16.0)),
+                Collider::Rect(Rect::from_pos(16.0, 0.0, 32.0, 16.0)),
+            ]
+        );
+    }
+}
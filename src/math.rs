@@ -55,6 +55,41 @@ impl Rect {
         }
     }
 
+    /// Smallest rectangle covering both.
+    pub fn union(&self, other: &Self) -> Self {
+        Self::from_pos(
+            self.left().min(other.left()),
+            self.top().min(other.top()),
+            self.right().max(other.right()),
+            self.bottom().max(other.bottom()),
+        )
+    }
+
+    /// Whether the segment from `a` to `b` passes through the inside of the rectangle. Running
+    /// along an edge or through a corner doesn't count, same as [`Rectangle::contains`].
+    pub fn overlaps_segment(&self, a: Vec2, b: Vec2) -> bool {
+        // clip the segment against each edge in turn
+        let d = b - a;
+        let (mut t0, mut t1) = (0.0f32, 1.0f32);
+        for (p, q) in [
+            (-d.x, a.x - self.left()),
+            (d.x, self.right() - a.x),
+            (-d.y, a.y - self.top()),
+            (d.y, self.bottom() - a.y),
+        ] {
+            if p == 0.0 {
+                if q <= 0.0 {
+                    return false;
+                }
+            } else if p < 0.0 {
+                t0 = t0.max(q / p);
+            } else {
+                t1 = t1.min(q / p);
+            }
+        }
+        t0 < t1
+    }
+
     pub fn translate_x(&self, offset: f32) -> Self {
         Self {
             x: self.x + offset,
@@ -71,6 +106,73 @@ impl Rect {
     }
 }
 
+/// Whether `point` is inside the polygon, which can be concave. The last point joins back up
+/// with the first.
+pub fn polygon_contains(points: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
 pub fn extend3d_to_uvec2(e: &Extent3d) -> UVec2 {
     UVec2::new(e.width as u32, e.height as u32)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Rect {
+        Rect::from_pos(0.0, 0.0, 10.0, 10.0)
+    }
+
+    #[test]
+    fn touching_rects_dont_overlap() {
+        assert!(!square().contains(&Rect::from_pos(10.0, 0.0, 20.0, 10.0)));
+        assert!(!square().contains(&Rect::from_pos(0.0, 10.0, 10.0, 20.0)));
+        assert!(square().contains(&Rect::from_pos(9.0, 9.0, 20.0, 20.0)));
+    }
+
+    #[test]
+    fn segments_along_edges_dont_overlap() {
+        let rect = square();
+        // along the top edge, and through a corner
+        assert!(!rect.overlaps_segment(Vec2::new(-5.0, 0.0), Vec2::new(15.0, 0.0)));
+        assert!(!rect.overlaps_segment(Vec2::new(-5.0, 5.0), Vec2::new(5.0, -5.0)));
+        // ending right on the left edge
+        assert!(!rect.overlaps_segment(Vec2::new(-5.0, 5.0), Vec2::new(0.0, 5.0)));
+    }
+
+    #[test]
+    fn segments_through_or_inside_overlap() {
+        let rect = square();
+        assert!(rect.overlaps_segment(Vec2::new(-5.0, 5.0), Vec2::new(15.0, 5.0)));
+        assert!(rect.overlaps_segment(Vec2::new(-5.0, 6.0), Vec2::new(6.0, -5.0)));
+        assert!(rect.overlaps_segment(Vec2::new(2.0, 2.0), Vec2::new(8.0, 3.0)));
+        assert!(!rect.overlaps_segment(Vec2::new(12.0, 2.0), Vec2::new(18.0, 3.0)));
+    }
+
+    #[test]
+    fn concave_polygon_contains() {
+        // an L, with the notch in the top right
+        let l = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(4.0, 0.0),
+            Vec2::new(4.0, 6.0),
+            Vec2::new(10.0, 6.0),
+            Vec2::new(10.0, 10.0),
+            Vec2::new(0.0, 10.0),
+        ];
+        assert!(polygon_contains(&l, Vec2::new(2.0, 2.0)));
+        assert!(polygon_contains(&l, Vec2::new(8.0, 8.0)));
+        assert!(!polygon_contains(&l, Vec2::new(8.0, 2.0)));
+        assert!(!polygon_contains(&l, Vec2::new(12.0, 8.0)));
+    }
+}
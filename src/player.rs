@@ -5,6 +5,7 @@ use glam::Vec2;
 use crate::{
     file_system::LoadError,
     graphics::{
+        assets::AssetLoader,
         pixel_art::{sprite::*, sprite_sheet::*},
         DrawParams, DrawQueue, Renderer,
     },
@@ -12,7 +13,7 @@ use crate::{
     math::{Rect, Rectangle},
 };
 
-use crate::LevelGeometry;
+use crate::{LevelGeometry, ENTITY_LAYER};
 
 pub struct Sprites {
     pub idle: Sprite,
@@ -84,7 +85,7 @@ pub enum State {
 }
 
 impl Player {
-    pub fn new(r: &mut Renderer) -> Result<Self, LoadError> {
+    pub fn new(r: &mut Renderer, position: Vec2) -> Result<Self, LoadError> {
         fn origin() -> Origin {
             Origin::Precise(Vec2::new(31.0, 22.0))
         }
@@ -193,7 +194,7 @@ impl Player {
                 h: rect_height,
             },
             velocity: Vec2::ZERO,
-            position: 100.0 * Vec2::ONE,
+            position,
             jump_count: 0,
             off_ground_timer: 0.0,
             on_ground_timer: 0.0,
@@ -280,22 +281,25 @@ impl Player {
             self.velocity.y = 0.0;
         }
         if self.velocity.y > 0.0
-            && !level_geometry.top_ladder_colliding(self.global_rect())
+            && !level_geometry.platform_colliding(self.global_rect())
             && level_geometry
-                .top_ladder_colliding(self.global_rect().translate_y(self.velocity.y * delta))
+                .platform_colliding(self.global_rect().translate_y(self.velocity.y * delta))
         {
             self.position.y = self.position.y.floor();
 
-            while !level_geometry.top_ladder_colliding(self.global_rect().translate_y(1.0)) {
+            while !level_geometry.platform_colliding(self.global_rect().translate_y(1.0)) {
                 self.position.y += 1.0;
             }
             self.velocity.y = 0.0;
         }
         self.position.y += self.velocity.y * delta;
 
-        if level_geometry.colliding(self.global_rect().translate_y(1.0))
-            || (!level_geometry.top_ladder_colliding(self.global_rect())
-                && level_geometry.top_ladder_colliding(self.global_rect().translate_y(1.0)))
+        let on_slope = self.land_on_slopes(delta, level_geometry);
+
+        if on_slope
+            || level_geometry.colliding(self.global_rect().translate_y(1.0))
+            || (!level_geometry.platform_colliding(self.global_rect())
+                && level_geometry.platform_colliding(self.global_rect().translate_y(1.0)))
         {
             self.off_ground_timer = 0.0;
             self.on_ground_timer = f32::min(self.on_ground_timer + delta, BOUNCE_TIME);
@@ -305,6 +309,41 @@ impl Player {
         }
     }
 
+    /// Stands the player on the slope under their feet, if they've sunk into it or are walking
+    /// down it, and returns whether they're stood on one.
+    fn land_on_slopes(&mut self, delta: f32, level_geometry: &LevelGeometry) -> bool {
+        if self.velocity.y < 0.0 {
+            return false;
+        }
+
+        let rect = self.global_rect();
+        let feet = rect.x + rect.w / 2.0;
+        // while on the ground, follow slopes down as far as walking can drop down a 45 degree
+        // one in a frame. The extra pixel is so standing right on the floor counts
+        let reach = if self.on_ground() {
+            H_SPEED * delta
+        } else {
+            0.0
+        } + 1.0;
+        let floor = level_geometry
+            .slope_collisions(Rect {
+                h: rect.h + reach,
+                ..rect
+            })
+            .iter()
+            .filter(|slope| slope.rect.left() <= feet && feet < slope.rect.right())
+            .map(|slope| slope.floor_at(feet))
+            .filter(|floor| *floor <= rect.bottom() + reach)
+            .reduce(f32::min);
+
+        let Some(floor) = floor else {
+            return false;
+        };
+        self.position.y += floor - rect.bottom();
+        self.velocity.y = 0.0;
+        true
+    }
+
     pub fn update(&mut self, delta: f32, input: &Input, level_geometry: &LevelGeometry) {
         match &mut self.state {
             State::Idle => {
@@ -397,11 +436,13 @@ impl Player {
 
     pub fn draw(&self) -> DrawQueue {
         let mut dq = DrawQueue::new();
+        dq.set_layer(ENTITY_LAYER);
         let mut params = DrawParams {
             position: self.position,
             flip_x: self.flipped,
             flip_y: false,
             camera_locked: false,
+            ..Default::default()
         };
 
         match &self.state {
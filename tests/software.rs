@@ -0,0 +1,61 @@
+//! Renders a small scene with the software renderer and compares it to `tests/golden/`. Set
+//! `UPDATE_GOLDEN=1` to write the current output over the golden image instead.
+
+use cowboy_dot_exe::graphics::{
+    assets::AssetLoader,
+    pixel_art::{sprite::Origin, sprite_sheet::*},
+    software::SoftwareRenderer,
+    *,
+};
+use glam::Vec2;
+
+const GOLDEN: &str = "tests/golden/level.png";
+
+#[test]
+fn level_matches_golden() {
+    let mut renderer = SoftwareRenderer::new(427, 240);
+    let sprite = renderer
+        .load_sprite(Origin::TopLeft, "twelve_string/awake.png")
+        .unwrap();
+    let sheet = renderer
+        .load_sprite_sheet(
+            Origin::Precise(Vec2::new(31.0, 23.0)),
+            "player/run.png",
+            6,
+            FrameRate::Constant(0.1),
+            Orientation::Horizontal,
+        )
+        .unwrap();
+    let tile_map = renderer.load_tilemap("tiles/untitled.tmx").unwrap();
+
+    let mut dq = DrawQueue::new();
+    for layer in ["Backing", "Cave", "Inter", "Spikes", "Ladders"] {
+        dq.tile_layer(&tile_map, layer);
+    }
+    dq.sprite(&sprite, DrawParams::from_pos(Vec2::new(218.0, 180.0)));
+    dq.sheet(
+        &sheet,
+        2,
+        DrawParams {
+            flip_x: true,
+            ..DrawParams::from_pos(Vec2::new(150.0, 150.0))
+        },
+    );
+
+    renderer.update_camera(Vec2::new(30.0, 20.0));
+    let image = renderer.render(dq);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        image.save(GOLDEN).unwrap();
+        return;
+    }
+
+    let golden = image::open(GOLDEN).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), golden.dimensions());
+    let different = image
+        .pixels()
+        .zip(golden.pixels())
+        .filter(|(a, b)| a != b)
+        .count();
+    assert_eq!(different, 0, "{} pixels differ from {}", different, GOLDEN);
+}